@@ -0,0 +1,179 @@
+use clap::{App, Arg};
+use engine::planner;
+use engine::utils::Coords;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::prelude::*;
+
+#[derive(Deserialize)]
+struct ConfigAnchor {
+    #[allow(dead_code)]
+    id: u32,
+    pos: [f32; 3],
+}
+
+#[derive(Deserialize)]
+struct ConfigAnchorsFile {
+    anchor: Vec<ConfigAnchor>,
+}
+
+fn load_anchors(path: &str) -> Result<Vec<Coords>, String> {
+    let mut config = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut config))
+        .map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let file: ConfigAnchorsFile =
+        toml::from_str(&config).map_err(|e| format!("Invalid anchors file: {}", e))?;
+    Ok(file.anchor.iter().map(|a| Coords(a.pos)).collect())
+}
+
+fn parse_area(args: &clap::ArgMatches) -> Result<planner::Area, String> {
+    let bounds: Vec<f32> = args
+        .value_of("area")
+        .unwrap()
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid area: {}", e))?;
+    if bounds.len() != 4 {
+        return Err("Area should be given as x0,y0,x1,y1".to_string());
+    }
+    let number = |name: &str| -> Result<f32, String> {
+        args.value_of(name)
+            .unwrap()
+            .parse::<f32>()
+            .map_err(|e| format!("Invalid {}: {}", name, e))
+    };
+    let area = planner::Area {
+        min: [bounds[0], bounds[1]],
+        max: [bounds[2], bounds[3]],
+        step: number("step")?,
+        height: number("height")?,
+    };
+    area.check().map_err(|e| format!("Invalid area: {}", e))?;
+    Ok(area)
+}
+
+fn run(args: &clap::ArgMatches) -> Result<(), String> {
+    let anchors = load_anchors(args.value_of("anchors").unwrap())?;
+    let area = parse_area(args)?;
+    let config = planner::Config {
+        range_limit: args
+            .value_of("range")
+            .unwrap()
+            .parse()
+            .map_err(|e| format!("Invalid range: {}", e))?,
+        range_sigma: args
+            .value_of("deviation")
+            .unwrap()
+            .parse()
+            .map_err(|e| format!("Invalid deviation: {}", e))?,
+    };
+    let grid = planner::evaluate(&anchors, &area, &config).map_err(|e| e.to_string())?;
+    let output = match args.value_of("format").unwrap() {
+        "pgm" => {
+            let max_gdop: f32 = args
+                .value_of("max_gdop")
+                .unwrap()
+                .parse()
+                .map_err(|e| format!("Invalid max_gdop: {}", e))?;
+            grid.to_pgm(max_gdop)
+        }
+        _ => grid.to_csv(&config).into_bytes(),
+    };
+    match args.value_of("output") {
+        Some(path) => File::create(path).and_then(|mut f| f.write_all(&output)),
+        None => std::io::stdout().write_all(&output),
+    }
+    .map_err(|e| format!("Could not write output: {}", e))?;
+
+    eprintln!(
+        "{} anchors, {} of {} points unsolvable, worst GDOP {:?}",
+        anchors.len(),
+        grid.uncovered(),
+        grid.cells.len(),
+        grid.worst_gdop()
+    );
+    if let Some(s) = planner::suggest_anchor(&anchors, &area, &config).map_err(|e| e.to_string())? {
+        eprintln!(
+            "suggested anchor at {:?}: {} points unsolvable, worst GDOP {:?}",
+            s.pos.0, s.uncovered, s.worst_gdop
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = App::new("RTLS anchor planner")
+        .version("0.1")
+        .about("Evaluate anchor layout, GDOP and coverage over area")
+        .arg(
+            Arg::with_name("anchors")
+                .short("a")
+                .long("anchors")
+                .value_name("FILE")
+                .help("Anchors description file")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("area")
+                .long("area")
+                .value_name("X0,Y0,X1,Y1")
+                .help("evaluated rectangle, minimum corner first")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("step")
+                .short("s")
+                .long("step")
+                .default_value("1")
+                .help("grid step [m]"),
+        )
+        .arg(
+            Arg::with_name("height")
+                .long("height")
+                .default_value("0")
+                .help("tags height [m]"),
+        )
+        .arg(
+            Arg::with_name("range")
+                .short("r")
+                .long("range")
+                .default_value("30")
+                .help("anchor range limit [m]"),
+        )
+        .arg(
+            Arg::with_name("deviation")
+                .short("d")
+                .long("deviation")
+                .default_value("0.1")
+                .help("range standard deviation, scales expected error [m]"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .possible_values(&["csv", "pgm"])
+                .default_value("csv")
+                .help("output format"),
+        )
+        .arg(
+            Arg::with_name("max_gdop")
+                .long("max_gdop")
+                .default_value("5")
+                .help("GDOP drawn black in image"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("output file, standard output by default"),
+        )
+        .get_matches();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
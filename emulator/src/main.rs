@@ -6,7 +6,7 @@ use futures::sync::mpsc;
 use clap::{App, Arg};
 use engine::device;
 use engine::measure;
-use engine::utils::{Coords, Trace};
+use engine::utils::Coords;
 use ndarray;
 use ndarray_linalg::*;
 use serde::{Deserialize, Serialize};
@@ -24,7 +24,7 @@ use devices::Device;
 
 fn create_dist_measure(d1: &dyn Device, d2: &dyn Device) -> measure::Distance {
     let ts = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-        Ok(n) => n.as_millis() as u32,
+        Ok(n) => n.as_millis() as u64,
         Err(_) => panic!("SystemTime before UNIX EPOCH!"),
     };
     measure::Distance {
@@ -143,16 +143,7 @@ fn send_anchors_descriptions(
 ) {
     for a in anchors.iter() {
         let pos = a.position();
-        let m = device::Description {
-            id: a.did(),
-            timestamp: 0,
-            pos: {
-                Trace {
-                    coords: Coords([pos[0], pos[0], pos[0]]),
-                    timestamp: 0,
-                }
-            },
-        };
+        let m = device::Description::from_coords(a.did(), Coords([pos[0], pos[1], pos[2]]), 0);
         let packet = Packet { cmd: 2, data: m };
         let txt = serde_json::to_string(&packet).unwrap();
         let msg = OwnedMessage::Text(txt);
@@ -187,18 +178,22 @@ fn measure_loop(args: &clap::ArgMatches, sink: mpsc::Sender<OwnedMessage>, print
     loop {
         for t in tags.iter_mut() {
             let tag: &mut dyn Device = &mut **t;
-            for a in anchors.iter() {
-                let anc: &dyn Device = &**a;
-                let m: measure::Distance = create_dist_measure(anc, tag);
-                let packet = Packet { cmd: 1, data: m };
-                let txt = serde_json::to_string(&packet).unwrap();
-                let msg = OwnedMessage::Text(txt);
-                // Send message to websocket server
-                stdin_sink
-                    .send(msg)
-                    .expect("Sending message across stdin channel.");
-                counter += 1;
-            }
+            // measures of single tag make one epoch
+            let batch: Vec<measure::Distance> = anchors
+                .iter()
+                .map(|a| create_dist_measure(&**a, tag))
+                .collect();
+            counter += batch.len();
+            let packet = Packet {
+                cmd: 3,
+                data: batch,
+            };
+            let txt = serde_json::to_string(&packet).unwrap();
+            let msg = OwnedMessage::Text(txt);
+            // Send message to websocket server
+            stdin_sink
+                .send(msg)
+                .expect("Sending message across stdin channel.");
             tag.update(dt);
         }
 
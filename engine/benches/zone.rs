@@ -0,0 +1,137 @@
+//! Cost of a single distance measure processing in zones of growing size.
+//!
+//! With indexed storage time per measure should stay flat while the number
+//! of devices and links grows. `linear` is the storage zone used before,
+//! plain vectors scanned on every measure, kept as a baseline.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use engine::measure;
+use engine::tracker::{least_squares, Range};
+use engine::utils::{Coords, DevId, Timestamp};
+use engine::zone::{ExitCode, Zone};
+
+/// Anchors in range of every tag
+const ANCHORS_PER_TAG: u32 = 8;
+const ANCHOR_SPACING: f32 = 10.0;
+const TAG_ID_BASE: DevId = 100_000;
+
+fn anchor_position(i: u32, side: u32) -> Coords {
+    Coords([
+        (i % side) as f32 * ANCHOR_SPACING,
+        (i / side) as f32 * ANCHOR_SPACING,
+        3.0,
+    ])
+}
+
+/// Devices and links in vectors, every lookup scans them
+#[derive(Default)]
+struct LinearZone {
+    devices: Vec<(DevId, Coords, bool)>,
+    measures: Vec<measure::List>,
+}
+
+impl LinearZone {
+    fn add_anchor(&mut self, id: DevId, pos: Coords) {
+        self.devices.push((id, pos, true));
+    }
+
+    fn add_measure(&mut self, id1: DevId, id2: DevId, distance: f32, ts: Timestamp) {
+        let meas = measure::Distance::new([id1, id2], ts, distance);
+        let linked = |m: &&mut measure::List| (m.id(0), m.id(1)) == (id1.min(id2), id1.max(id2));
+        match self.measures.iter_mut().find(linked) {
+            Some(list) => list.update(meas),
+            None => self.measures.push(measure::List::new(meas)),
+        }
+        for &id in [id1, id2].iter() {
+            if !self.devices.iter().any(|d| d.0 == id) {
+                self.devices.push((id, Coords([0.0; 3]), false));
+            }
+            self.update_position(id, ts);
+        }
+    }
+
+    fn update_position(&mut self, id: DevId, ts: Timestamp) {
+        let idx = self.devices.iter().position(|d| d.0 == id).unwrap();
+        if self.devices[idx].2 {
+            return;
+        }
+        let ranges: Vec<Range> = self
+            .measures
+            .iter()
+            .filter(|m| m.id(0) == id || m.id(1) == id)
+            .filter_map(|m| {
+                let other = self.devices.iter().find(|d| d.0 == m.other(id))?;
+                Some(Range::new(other.1, m.estimate(ts))).filter(|_| other.2)
+            })
+            .collect();
+        self.devices[idx].1 = least_squares::solve(&self.devices[idx].1, &ranges);
+    }
+}
+
+/// Zone where each tag has already ranged against its nearest anchors
+fn build_zone(tags: u32, anchors: u32) -> Zone {
+    let side = (anchors as f32).sqrt().ceil() as u32;
+    let mut zone = Zone::new(0);
+    for a in 0..anchors {
+        assert_eq!(zone.add_anchor(a, anchor_position(a, side)), ExitCode::Ok);
+    }
+    for t in 0..tags {
+        for k in 0..ANCHORS_PER_TAG {
+            let anchor = (t + k) % anchors;
+            for ts in 0..2 {
+                zone.add_measure(TAG_ID_BASE + t, anchor, 5.0, ts, true);
+            }
+        }
+    }
+    zone
+}
+
+fn build_linear_zone(tags: u32, anchors: u32) -> LinearZone {
+    let side = (anchors as f32).sqrt().ceil() as u32;
+    let mut zone = LinearZone::default();
+    for a in 0..anchors {
+        zone.add_anchor(a, anchor_position(a, side));
+    }
+    for t in 0..tags {
+        for k in 0..ANCHORS_PER_TAG {
+            let anchor = (t + k) % anchors;
+            for ts in 0..2 {
+                zone.add_measure(TAG_ID_BASE + t, anchor, 5.0, ts);
+            }
+        }
+    }
+    zone
+}
+
+const SIZES: [(u32, u32); 3] = [(20, 30), (200, 60), (2000, 300)];
+
+fn add_measure(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_measure");
+    for &(tags, anchors) in SIZES.iter() {
+        let id = format!("{}tags_{}anchors", tags, anchors);
+        let mut zone = build_zone(tags, anchors);
+        let mut ts = 10;
+        let mut t = 0;
+        group.bench_function(BenchmarkId::new("indexed", &id), |b| {
+            b.iter(|| {
+                ts += 1;
+                t = (t + 1) % tags;
+                zone.add_measure(TAG_ID_BASE + t, t % anchors, 5.0, ts, true)
+            })
+        });
+        let mut zone = build_linear_zone(tags, anchors);
+        let mut ts = 10;
+        let mut t = 0;
+        group.bench_function(BenchmarkId::new("linear", &id), |b| {
+            b.iter(|| {
+                ts += 1;
+                t = (t + 1) % tags;
+                zone.add_measure(TAG_ID_BASE + t, t % anchors, 5.0, ts)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, add_measure);
+criterion_main!(benches);
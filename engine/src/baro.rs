@@ -0,0 +1,171 @@
+//! Barometric height of tags.
+//!
+//! Pressure difference between tag and reference anchor gives height above
+//! the anchor, which is fused into z poorly observed by ranges and used for
+//! floor determination. Tag barometers disagree by metres, so every tag
+//! gets own height offset, averaged over first fixes or set at known height.
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{DevId, Timestamp};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// anchor measuring reference pressure
+    pub reference: Option<DevId>,
+    /// barometric height deviation [m]
+    pub sigma: f32,
+    /// deviation of height from ranges [m]
+    pub range_sigma: f32,
+    /// fixes averaged for offset of uncalibrated tag, 0 disables it
+    pub calibration_fixes: usize,
+    /// height of the lowest floor level [m]
+    pub ground: f32,
+    pub floor_height: f32,
+    /// pressure older than that is not used [ms]
+    pub max_age: Timestamp,
+}
+
+/// Barometer memory kept per device
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct State {
+    /// last pressure [Pa] with its time
+    pressure: Option<(f32, Timestamp)>,
+    /// subtracted from raw height [m]
+    offset: Option<f32>,
+    calibration_sum: f32,
+    calibration_fixes: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            reference: None,
+            sigma: 0.3,
+            range_sigma: 1.5,
+            calibration_fixes: 20,
+            ground: 0.0,
+            floor_height: 3.0,
+            max_age: 5_000,
+        }
+    }
+}
+
+/// Height of `pressure` above `reference` one, international barometric
+/// formula [m]
+pub fn relative_height(pressure: f32, reference: f32) -> f32 {
+    44_330.8 * (1.0 - (pressure / reference).powf(0.190_263))
+}
+
+impl Config {
+    /// Deviations and floor height should be positive, all values finite
+    pub fn is_valid(&self) -> bool {
+        let positive = |v: f32| v.is_finite() && v > 0.0;
+        positive(self.sigma)
+            && positive(self.range_sigma)
+            && positive(self.floor_height)
+            && self.ground.is_finite()
+    }
+
+    /// Floor number of height, 0 at ground level
+    pub fn floor(&self, z: f32) -> i32 {
+        ((z - self.ground) / self.floor_height.max(f32::EPSILON)).floor() as i32
+    }
+
+    /// Blend height from ranges with barometric one by their deviations
+    pub fn fuse(&self, range_z: f32, baro_z: f32) -> f32 {
+        let (wr, wb) = (1.0 / self.range_sigma.powi(2), 1.0 / self.sigma.powi(2));
+        (range_z * wr + baro_z * wb) / (wr + wb)
+    }
+}
+
+impl State {
+    pub fn set_pressure(&mut self, pressure: f32, timestamp: Timestamp) {
+        self.pressure = Some((pressure, timestamp));
+    }
+
+    pub fn offset(&self) -> Option<f32> {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, offset: f32) {
+        self.offset = Some(offset);
+    }
+
+    /// Height before offset correction, above reference at `reference_z`
+    pub fn raw_height(
+        &self,
+        config: &Config,
+        reference: (f32, f32),
+        timestamp: Timestamp,
+    ) -> Option<f32> {
+        let (pressure, at) = self.pressure?;
+        if timestamp.saturating_sub(at) > config.max_age {
+            return None;
+        }
+        let (reference_pressure, reference_z) = reference;
+        Some(reference_z + relative_height(pressure, reference_pressure))
+    }
+
+    /// Calibrated height
+    pub fn height(
+        &self,
+        config: &Config,
+        reference: (f32, f32),
+        timestamp: Timestamp,
+    ) -> Option<f32> {
+        Some(self.raw_height(config, reference, timestamp)? - self.offset?)
+    }
+
+    /// Average difference to fix heights, returns offset once it's known
+    pub fn calibrate(&mut self, config: &Config, raw_height: f32, fix_z: f32) -> Option<f32> {
+        if self.offset.is_some() || config.calibration_fixes == 0 {
+            return None;
+        }
+        self.calibration_sum += raw_height - fix_z;
+        self.calibration_fixes += 1;
+        if self.calibration_fixes < config.calibration_fixes {
+            return None;
+        }
+        let offset = self.calibration_sum / self.calibration_fixes as f32;
+        self.offset = Some(offset);
+        Some(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressure_difference_gives_height() {
+        // about 12 Pa per metre near sea level
+        let h = relative_height(101_325.0 - 36.0, 101_325.0);
+        assert!((h - 3.0).abs() < 0.05);
+        let config = Config {
+            calibration_fixes: 3,
+            ..Config::default()
+        };
+        let mut state = State::default();
+        state.set_pressure(101_325.0 - 36.0, 1000);
+        let reference = (101_325.0, 1.0);
+        let raw = state.raw_height(&config, reference, 1000).unwrap();
+        assert!(state.height(&config, reference, 1000).is_none());
+        assert_eq!(state.calibrate(&config, raw, 1.5), None);
+        assert_eq!(state.calibrate(&config, raw, 2.5), None);
+        let offset = state.calibrate(&config, raw, 2.0).unwrap();
+        assert!((offset - 2.0).abs() < 0.05);
+        let z = state.height(&config, reference, 1000).unwrap();
+        assert!((z - 2.0).abs() < 0.01);
+        assert_eq!(config.floor(z), 0);
+        assert_eq!(config.floor(7.0), 2);
+        assert!(state.height(&config, reference, 10_000).is_none());
+        assert!(config.is_valid());
+        let flat = Config {
+            sigma: 0.0,
+            ..Config::default()
+        };
+        assert!(!flat.is_valid());
+    }
+}
@@ -0,0 +1,96 @@
+//! Time source of the engine.
+//!
+//! Positions are calculated with timestamps of measures, clock answers what
+//! time it is between them: for liveness checks, periodic publishing and
+//! extrapolation. Manual clock lets tests and replays run faster than real
+//! time, deterministically.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::utils::Timestamp;
+
+pub trait Clock: Send + Sync {
+    /// Current time [ms]
+    fn now(&self) -> Timestamp;
+}
+
+/// Wall clock, milliseconds since UNIX epoch
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+/// Clock moved only on request, clones share the same time
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock(Arc<AtomicU64>);
+
+/// Tells when periodic action, eg. publishing positions, should run
+#[derive(Copy, Clone, Debug)]
+pub struct Ticker {
+    interval: Timestamp,
+    next: Timestamp,
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        let since_epoch = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_else(|_| Duration::from_secs(0));
+        since_epoch.as_millis() as Timestamp
+    }
+}
+
+impl ManualClock {
+    pub fn new(start: Timestamp) -> ManualClock {
+        ManualClock(Arc::new(AtomicU64::new(start)))
+    }
+
+    pub fn set(&self, now: Timestamp) {
+        self.0.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, ms: Timestamp) {
+        self.0.fetch_add(ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timestamp {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Ticker {
+    pub fn new(interval: Timestamp) -> Ticker {
+        Ticker { interval, next: 0 }
+    }
+
+    /// True once per interval, missed ticks are not repeated
+    pub fn due(&mut self, now: Timestamp) -> bool {
+        if now < self.next {
+            return false;
+        }
+        self.next = now + self.interval;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_shared_by_clones() {
+        let clock = ManualClock::new(1000);
+        let shared: Arc<dyn Clock> = Arc::new(clock.clone());
+        clock.advance(500);
+        assert_eq!(shared.now(), 1500);
+        let mut ticker = Ticker::new(1000);
+        assert!(ticker.due(shared.now()));
+        clock.advance(999);
+        assert!(!ticker.due(shared.now()));
+        clock.set(10_000);
+        assert!(ticker.due(shared.now()));
+        assert!(!ticker.due(shared.now()));
+    }
+}
@@ -1,26 +1,100 @@
 use serde_derive::{Deserialize, Serialize};
-use std::collections::VecDeque;
 
-use crate::measure;
-use crate::utils::{Coords, DevId, Scent, Timestamp, Trace};
-use ndarray::prelude::*;
-use ndarray::*;
-use ndarray_linalg::Norm;
-use ndarray_linalg::Solve;
+use crate::baro;
+use crate::gating;
+use crate::geo;
+use crate::inertial;
+use crate::stationary;
+use crate::tracker;
+use crate::utils::{Coords, DevId, Scent, Timestamp, Trace, ZoneId};
 
 const POSITION_TRACE_DEPTH: usize = 3;
+/// Longest time position is extrapolated for, also the longest gap
+/// between positions used to estimate velocity [ms]
+const EXTRAPOLATION_LIMIT: Timestamp = 2_000;
+
+/// Device state based on time elapsed since its last measurement
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    #[default]
+    Active,
+    Stale,
+    Lost,
+}
+
+/// Inactivity times after which device becomes stale or lost [ms]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct LivenessTimeouts {
+    pub stale: Timestamp,
+    pub lost: Timestamp,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Description {
     pub pos: Trace,
     pub id: DevId,
     pub timestamp: Timestamp, // last activity timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geo: Option<geo::Wgs84>,
+    #[serde(default)]
+    pub liveness: Liveness,
+    /// zone owning the device, also selects zone for anchor registration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone: Option<ZoneId>,
+    /// false while device is detected as stationary
+    #[serde(default = "moving_default")]
+    pub moving: bool,
+    /// floor number from height, when barometer is calibrated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub floor: Option<i32>,
+}
+
+/// Anchors have fixed, known position, tags are tracked
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum Role {
+    Anchor,
+    Tag,
 }
 
+/// User provided device properties, not used for positioning
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// device class, eg. "forklift", "worker"
+    #[serde(default)]
+    pub class: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Data {
     scent: Scent,
     id: DevId,
+    role: Role,
+    metadata: Metadata,
     timestamp: Timestamp, // last activity timestamp
+    lost: bool,           // lost state already reported
+    track: tracker::State,
+    fixed: bool, // position comes from measures, not a default
+    gate: gating::State,
+    motion: stationary::State,
+    sigma: Option<f32>, // position deviation, when known
+    inertial: inertial::State,
+    baro: baro::State,
+}
+
+fn moving_default() -> bool {
+    true
+}
+
+impl Default for LivenessTimeouts {
+    fn default() -> LivenessTimeouts {
+        LivenessTimeouts {
+            stale: 5_000,
+            lost: 30_000,
+        }
+    }
 }
 
 impl Description {
@@ -28,18 +102,61 @@ impl Description {
         Description {
             id: dev.id,
             pos: dev.estimate_position(timestamp),
-            timestamp: timestamp,
+            timestamp: dev.timestamp,
+            geo: None,
+            liveness: Liveness::Active,
+            zone: None,
+            moving: dev.is_moving(),
+            floor: None,
         }
     }
 
+    /// Description of device placed at given coordinates, eg. anchor
+    pub fn from_coords(id: DevId, coords: Coords, timestamp: Timestamp) -> Description {
+        Description {
+            id,
+            pos: Trace { coords, timestamp },
+            timestamp,
+            geo: None,
+            liveness: Liveness::Active,
+            zone: None,
+            moving: false,
+            floor: None,
+        }
+    }
+
+    pub fn with_liveness(mut self, liveness: Liveness) -> Description {
+        self.liveness = liveness;
+        self
+    }
+
+    pub fn with_floor(mut self, floor: Option<i32>) -> Description {
+        self.floor = floor;
+        self
+    }
+
+    pub fn with_zone(mut self, zone: ZoneId) -> Description {
+        self.zone = Some(zone);
+        self
+    }
+
+    /// Fill geodetic coordinates using zone reference point
+    pub fn with_geo(mut self, reference: Option<&geo::Reference>) -> Description {
+        self.geo = reference.map(|r| r.to_wgs84(&self.pos.coords));
+        self
+    }
+
     pub fn id(&self) -> DevId {
         self.id
     }
 }
 
 impl Data {
+    /// Device with unknown position, it is found by acquisition
     pub fn new(id: DevId) -> Data {
-        Data::new_with_pos(id, [0, 0, 0])
+        let mut dev = Data::new_with_pos(id, [0, 0, 0]);
+        dev.fixed = false;
+        dev
     }
 
     pub fn id(&self) -> DevId {
@@ -47,39 +164,166 @@ impl Data {
     }
 
     pub fn new_with_pos(id: DevId, pos: [i32; 3]) -> Data {
+        let coords = Coords([pos[0] as f32, pos[1] as f32, pos[2] as f32]);
+        Data::new_with_role(id, coords, Role::Tag)
+    }
+
+    pub fn new_with_role(id: DevId, coords: Coords, role: Role) -> Data {
         let pos = Trace {
             timestamp: 0,
-            coords: Coords([pos[0] as f32, pos[1] as f32, pos[2] as f32]),
+            coords,
         };
         let mut dev = Data {
             id: id,
+            role,
+            metadata: Metadata::default(),
             timestamp: 0,
+            lost: false,
             scent: Scent::with_capacity(POSITION_TRACE_DEPTH),
+            track: tracker::State::None,
+            fixed: true,
+            gate: gating::State::default(),
+            motion: stationary::State::default(),
+            sigma: None,
+            inertial: inertial::State::default(),
+            baro: baro::State::default(),
         };
         dev.scent.add(pos);
         dev
     }
 
-    pub fn calc_position(
-        &self,
-        _measures: &Vec<&measure::List>,
-        _devices: &Vec<&Data>,
-        timestamp: u32,
-    ) -> Trace {
-        // new position calculation or extrapolation when calculation is fresher than measurements
-        // fake calculation or estimation when calculation is fresher than measurements
-        //todo: Add position calculation
-        let mut new_pos = self.scent.get(0).unwrap().clone();
-        new_pos.coords[0] += 0.9;
-        new_pos.coords[1] += 0.55;
-        if new_pos.coords[0] > 700.0 {
-            new_pos.coords[0] = 0.0;
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = metadata;
+    }
+
+    /// Place device at new position, forgetting its track
+    pub fn relocate(&mut self, coords: Coords, timestamp: Timestamp) {
+        self.scent = Scent::with_capacity(POSITION_TRACE_DEPTH);
+        self.scent.add(Trace { coords, timestamp });
+        self.track = tracker::State::None;
+        self.fixed = true;
+        self.gate.accept();
+        self.motion = stationary::State::default();
+        self.inertial.fixed(timestamp);
+    }
+
+    /// True once position was solved from measures or set explicitly
+    pub fn has_fix(&self) -> bool {
+        self.fixed
+    }
+
+    pub fn mark_fixed(&mut self) {
+        self.fixed = true;
+    }
+
+    pub fn gate_state(&self) -> &gating::State {
+        &self.gate
+    }
+
+    pub fn gate_state_mut(&mut self) -> &mut gating::State {
+        &mut self.gate
+    }
+
+    /// Number of fixes rejected as physically implausible
+    pub fn rejected_fixes(&self) -> u64 {
+        self.gate.rejected
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.motion.is_moving()
+    }
+
+    pub fn motion_state_mut(&mut self) -> &mut stationary::State {
+        &mut self.motion
+    }
+
+    pub fn inertial_state(&self) -> &inertial::State {
+        &self.inertial
+    }
+
+    pub fn inertial_state_mut(&mut self) -> &mut inertial::State {
+        &mut self.inertial
+    }
+
+    pub fn baro_state(&self) -> &baro::State {
+        &self.baro
+    }
+
+    pub fn baro_state_mut(&mut self) -> &mut baro::State {
+        &mut self.baro
+    }
+
+    /// Deviation of horizontal position [m], known after joint solution
+    pub fn position_sigma(&self) -> Option<f32> {
+        self.sigma
+    }
+
+    pub fn set_position_sigma(&mut self, sigma: Option<f32>) {
+        self.sigma = sigma;
+    }
+
+    /// Saved position, 0 is the newest one
+    pub fn recent_position(&self, age: usize) -> Option<&Trace> {
+        self.scent.get(age)
+    }
+
+    pub fn last_activity(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    /// Register device activity, returns true when device was lost before
+    pub fn touch(&mut self, timestamp: Timestamp) -> bool {
+        if timestamp > self.timestamp {
+            self.timestamp = timestamp;
         }
-        if new_pos.coords[1] > 700.0 {
-            new_pos.coords[1] = 0.0;
+        let recovered = self.lost;
+        self.lost = false;
+        recovered
+    }
+
+    /// Anchors are always active, their outage is found by anchor health
+    pub fn liveness(&self, now: Timestamp, timeouts: &LivenessTimeouts) -> Liveness {
+        let idle = now.saturating_sub(self.timestamp);
+        if self.role == Role::Anchor {
+            Liveness::Active
+        } else if idle >= timeouts.lost {
+            Liveness::Lost
+        } else if idle >= timeouts.stale {
+            Liveness::Stale
+        } else {
+            Liveness::Active
         }
-        new_pos.timestamp = timestamp;
-        new_pos
+    }
+
+    /// Mark device as lost, returns true when it wasn't reported yet
+    pub fn mark_lost(&mut self) -> bool {
+        let newly_lost = !self.lost;
+        self.lost = true;
+        newly_lost
+    }
+
+    pub fn track_state(&self) -> &tracker::State {
+        &self.track
+    }
+
+    pub fn set_track_state(&mut self, state: tracker::State) {
+        self.track = state;
+    }
+
+    /// Express device position and track in another frame
+    pub fn transform(&mut self, f: &dyn Fn(&Coords) -> Coords) {
+        for trace in self.scent.iter_mut() {
+            trace.coords = f(&trace.coords);
+        }
+        self.track.transform(f);
     }
 
     pub fn estimate_position(&self, timestamp: Timestamp) -> Trace {
@@ -88,7 +332,35 @@ impl Data {
         pos
     }
 
+    /// Position at given time, moved along recent velocity for a while
+    pub fn extrapolate_position(&self, timestamp: Timestamp) -> Trace {
+        let mut pos = self.estimate_position(timestamp);
+        let (last, prev) = match (self.scent.get(0), self.scent.get(1)) {
+            (Some(l), Some(p)) => (l, p),
+            _ => return pos,
+        };
+        let dt = last.timestamp.saturating_sub(prev.timestamp);
+        if dt == 0 || dt > EXTRAPOLATION_LIMIT {
+            return pos;
+        }
+        let ahead = timestamp
+            .saturating_sub(last.timestamp)
+            .min(EXTRAPOLATION_LIMIT);
+        let k = ahead as f32 / dt as f32;
+        for i in 0..3 {
+            pos.coords[i] = last.coords[i] + (last.coords[i] - prev.coords[i]) * k;
+        }
+        pos
+    }
+
+    /// Save new position, keeping single one per timestamp
     pub fn save_position(&mut self, pos: Trace) {
+        if let Some(last) = self.scent.iter_mut().next() {
+            if last.timestamp == pos.timestamp {
+                *last = pos;
+                return;
+            }
+        }
         self.scent.add(pos);
     }
 }
@@ -0,0 +1,126 @@
+//! Notifications about everything zone learns, delivered to subscribers as
+//! it happens, so embedders don't need to poll for positions.
+
+use serde_derive::{Deserialize, Serialize};
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::device::Role;
+use crate::health;
+use crate::map::Polygon;
+use crate::utils::{Coords, DevId, Trace, ZoneId};
+
+/// Named area, entering and leaving it is reported
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Geofence {
+    pub name: String,
+    pub area: Polygon,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    PositionUpdated {
+        zone: ZoneId,
+        id: DevId,
+        pos: Trace,
+    },
+    NewLink {
+        zone: ZoneId,
+        ids: [DevId; 2],
+    },
+    DeviceCreated {
+        zone: ZoneId,
+        id: DevId,
+        role: Role,
+    },
+    DeviceLost {
+        zone: ZoneId,
+        id: DevId,
+    },
+    DeviceRecovered {
+        zone: ZoneId,
+        id: DevId,
+    },
+    GeofenceEntered {
+        zone: ZoneId,
+        id: DevId,
+        fence: String,
+    },
+    GeofenceLeft {
+        zone: ZoneId,
+        id: DevId,
+        fence: String,
+    },
+    /// fix implying impossible movement, held or clamped
+    FixRejected {
+        zone: ZoneId,
+        id: DevId,
+        speed: f32,
+        accel: f32,
+        clamped: bool,
+    },
+    /// track started again from consistent fixes rejected before
+    TrackReinitialised {
+        zone: ZoneId,
+        id: DevId,
+    },
+    /// anchor residuals became biased, noisy, missing or healthy again
+    AnchorStatus {
+        zone: ZoneId,
+        id: DevId,
+        status: health::Status,
+    },
+    /// anchor seems displaced from configured position by `offset`
+    AnchorMoved {
+        zone: ZoneId,
+        id: DevId,
+        offset: Coords,
+    },
+    /// device parameter estimated by the engine, eg. sensor offset
+    Calibrated {
+        zone: ZoneId,
+        id: DevId,
+        parameter: String,
+        value: f32,
+    },
+    Handover {
+        id: DevId,
+        from: ZoneId,
+        to: ZoneId,
+    },
+}
+
+pub trait Subscriber: Send {
+    fn notify(&mut self, event: &Event);
+}
+
+/// Channel subscriber, disconnected receiver is ignored
+impl Subscriber for mpsc::Sender<Event> {
+    fn notify(&mut self, event: &Event) {
+        let _ = self.send(event.clone());
+    }
+}
+
+impl<S: Subscriber + ?Sized> Subscriber for Box<S> {
+    fn notify(&mut self, event: &Event) {
+        (**self).notify(event);
+    }
+}
+
+/// Subscriber shared between zones, eg. by zone manager
+impl<S: Subscriber + ?Sized> Subscriber for Arc<Mutex<S>> {
+    fn notify(&mut self, event: &Event) {
+        if let Ok(mut s) = self.lock() {
+            s.notify(event);
+        }
+    }
+}
+
+impl Geofence {
+    pub fn new(name: &str, area: Polygon) -> Geofence {
+        Geofence {
+            name: name.to_string(),
+            area,
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Localisation from radio fingerprints, for areas without enough anchors.
+//!
+//! Database keeps signal values to anchors, RSSI or ranges, recorded at
+//! surveyed points. Position is weighted average of k records nearest to
+//! current values in signal space.
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use crate::utils::{Coords, DevId, Timestamp};
+
+/// Kind of values kept in database
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Signal {
+    /// received signal strength [dBm]
+    Rssi,
+    /// range [m]
+    Range,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// records averaged
+    pub k: usize,
+    /// difference counted for anchor seen only by one side
+    pub missing: f32,
+    /// anchors shared with record needed to compare with it
+    pub min_common: usize,
+    /// values older than that are not used [ms]
+    pub max_age: Timestamp,
+    /// deviation of located position [m], weight of it in tracker
+    pub sigma: f32,
+}
+
+/// Signal values to anchors at surveyed point
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Fingerprint {
+    pub pos: Coords,
+    pub values: BTreeMap<DevId, f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Database {
+    pub signal: Signal,
+    pub records: Vec<Fingerprint>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            k: 3,
+            missing: 10.0,
+            min_common: 2,
+            max_age: 5_000,
+            sigma: 1.0,
+        }
+    }
+}
+
+impl Config {
+    /// At least one record averaged, positive deviation, finite values
+    pub fn is_valid(&self) -> bool {
+        self.k >= 1 && self.missing.is_finite() && self.sigma.is_finite() && self.sigma > 0.0
+    }
+}
+
+impl Database {
+    pub fn new(signal: Signal) -> Database {
+        Database {
+            signal,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, pos: Coords, values: BTreeMap<DevId, f32>) {
+        self.records.push(Fingerprint { pos, values });
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+
+    pub fn load<R: Read>(reader: R) -> serde_json::Result<Database> {
+        serde_json::from_reader(reader)
+    }
+
+    /// Distance in signal space, `None` when too few anchors are shared
+    fn distance(
+        config: &Config,
+        a: &BTreeMap<DevId, f32>,
+        b: &BTreeMap<DevId, f32>,
+    ) -> Option<f32> {
+        let mut common = 0;
+        let mut sum = 0.0;
+        for (id, va) in a.iter() {
+            match b.get(id) {
+                Some(vb) => {
+                    common += 1;
+                    sum += (va - vb).powi(2);
+                }
+                None => sum += config.missing.powi(2),
+            }
+        }
+        sum += b.keys().filter(|id| !a.contains_key(id)).count() as f32 * config.missing.powi(2);
+        if common < config.min_common {
+            return None;
+        }
+        Some(sum.sqrt())
+    }
+
+    /// Weighted average of k nearest records
+    pub fn locate(&self, config: &Config, values: &BTreeMap<DevId, f32>) -> Option<Coords> {
+        let mut nearest: Vec<(f32, &Fingerprint)> = self
+            .records
+            .iter()
+            .filter_map(|f| Some((Database::distance(config, values, &f.values)?, f)))
+            .collect();
+        nearest.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        nearest.truncate(config.k.max(1));
+        if nearest.is_empty() {
+            return None;
+        }
+        let mut pos = Coords([0.0; 3]);
+        let mut total = 0.0;
+        for (d, f) in nearest.iter() {
+            let w = 1.0 / (d + 1e-3);
+            for i in 0..3 {
+                pos[i] += w * f.pos[i];
+            }
+            total += w;
+        }
+        for i in 0..3 {
+            pos[i] /= total;
+        }
+        Some(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rssi(values: &[(DevId, f32)]) -> BTreeMap<DevId, f32> {
+        values.iter().cloned().collect()
+    }
+
+    #[test]
+    fn nearest_records_averaged() {
+        let mut db = Database::new(Signal::Rssi);
+        db.record(Coords([0.0, 0.0, 0.0]), rssi(&[(1, -40.0), (2, -70.0)]));
+        db.record(Coords([10.0, 0.0, 0.0]), rssi(&[(1, -70.0), (2, -40.0)]));
+        db.record(Coords([5.0, 0.0, 0.0]), rssi(&[(1, -55.0), (2, -55.0)]));
+        db.record(Coords([50.0, 0.0, 0.0]), rssi(&[(7, -40.0), (8, -40.0)]));
+        let config = Config {
+            k: 2,
+            ..Config::default()
+        };
+        let pos = db
+            .locate(&config, &rssi(&[(1, -42.0), (2, -68.0)]))
+            .unwrap();
+        assert!(pos[0] > 0.0 && pos[0] < 2.5);
+        assert!(db.locate(&config, &rssi(&[(9, -40.0)])).is_none());
+        let mut saved = Vec::new();
+        db.save(&mut saved).unwrap();
+        assert_eq!(Database::load(&saved[..]).unwrap(), db);
+        assert!(config.is_valid());
+        let blurred = Config {
+            sigma: f32::INFINITY,
+            ..config
+        };
+        assert!(!blurred.is_valid());
+    }
+}
@@ -0,0 +1,232 @@
+//! Physical plausibility of new fixes, limits of speed and acceleration
+//! configured per device class.
+//!
+//! Fix implying impossible movement is rejected or clamped. When several
+//! following fixes agree with each other, but not with the old track, the
+//! track is started again from them, so the tag doesn't get stuck.
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::utils::{Coords, Trace};
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct MotionLimits {
+    /// [m/s]
+    pub max_speed: f32,
+    /// [m/s^2]
+    pub max_accel: f32,
+}
+
+/// What to do with implausible fix
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// hold previous position
+    Reject,
+    /// move towards the fix as far as speed limit allows
+    Clamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// limits per `Metadata::class`
+    pub classes: BTreeMap<String, MotionLimits>,
+    /// limits of devices without class or with unknown one, no gating if none
+    pub default: Option<MotionLimits>,
+    pub action: Action,
+    /// consistent implausible fixes needed to start the track again
+    pub resume_fixes: usize,
+}
+
+/// Gating memory kept per device
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct State {
+    /// implausible fixes met so far
+    pub rejected: u64,
+    // implausible fixes since the last accepted one
+    candidates: Vec<Trace>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Accepted,
+    Rejected {
+        speed: f32,
+        accel: f32,
+    },
+    Clamped {
+        speed: f32,
+        accel: f32,
+    },
+    /// fix agrees with previous implausible ones, track starts from it
+    Reinitialised,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            classes: BTreeMap::new(),
+            default: None,
+            action: Action::Reject,
+            resume_fixes: 3,
+        }
+    }
+}
+
+fn velocity(from: &Trace, to: &Trace) -> Option<[f32; 3]> {
+    let dt = to.timestamp.checked_sub(from.timestamp)? as f32 / 1000.0;
+    if dt <= 0.0 {
+        return None;
+    }
+    let mut v = [0.0; 3];
+    for (i, vi) in v.iter_mut().enumerate() {
+        *vi = (to.coords[i] - from.coords[i]) / dt;
+    }
+    Some(v)
+}
+
+fn norm(v: &[f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+impl Config {
+    pub fn limits(&self, class: Option<&str>) -> Option<&MotionLimits> {
+        class
+            .and_then(|c| self.classes.get(c))
+            .or(self.default.as_ref())
+    }
+
+    /// Check if `next` fix is plausible after `last` (preceded by `prev`),
+    /// returns position to use and what happened
+    pub fn check(
+        &self,
+        limits: &MotionLimits,
+        state: &State,
+        last: &Trace,
+        prev: Option<&Trace>,
+        next: &Trace,
+    ) -> (Trace, Outcome) {
+        let v = match velocity(last, next) {
+            Some(v) => v,
+            None => return (*next, Outcome::Accepted),
+        };
+        let speed = norm(&v);
+        let dt = (next.timestamp - last.timestamp) as f32 / 1000.0;
+        let accel = match prev.and_then(|p| velocity(p, last)) {
+            Some(v_last) => norm(&[v[0] - v_last[0], v[1] - v_last[1], v[2] - v_last[2]]) / dt,
+            None => 0.0,
+        };
+        if speed <= limits.max_speed && accel <= limits.max_accel {
+            return (*next, Outcome::Accepted);
+        }
+        if self.consistent(limits, state, next) {
+            return (*next, Outcome::Reinitialised);
+        }
+        match self.action {
+            Action::Reject => {
+                let held = Trace {
+                    coords: last.coords,
+                    timestamp: next.timestamp,
+                };
+                (held, Outcome::Rejected { speed, accel })
+            }
+            Action::Clamp => {
+                let k = limits.max_speed / speed;
+                let mut coords = Coords([0.0; 3]);
+                for i in 0..3 {
+                    coords[i] = last.coords[i] + (next.coords[i] - last.coords[i]) * k;
+                }
+                let clamped = Trace {
+                    coords,
+                    timestamp: next.timestamp,
+                };
+                (clamped, Outcome::Clamped { speed, accel })
+            }
+        }
+    }
+
+    /// Recent implausible fixes and the new one make plausible track
+    fn consistent(&self, limits: &MotionLimits, state: &State, next: &Trace) -> bool {
+        let needed = self.resume_fixes.max(1);
+        if state.candidates.len() + 1 < needed {
+            return false;
+        }
+        let mut track: Vec<&Trace> = state.candidates.iter().rev().take(needed - 1).collect();
+        track.reverse();
+        track.push(next);
+        track.windows(2).all(|w| match velocity(w[0], w[1]) {
+            Some(v) => norm(&v) <= limits.max_speed,
+            None => true,
+        })
+    }
+}
+
+impl State {
+    pub fn accept(&mut self) {
+        self.candidates.clear();
+    }
+
+    /// Remember implausible fix, it may start new track later
+    pub fn reject(&mut self, fix: Trace, keep: usize) {
+        self.rejected += 1;
+        self.candidates.push(fix);
+        if self.candidates.len() > keep {
+            self.candidates.remove(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(x: f32, timestamp: u64) -> Trace {
+        Trace {
+            coords: Coords([x, 0.0, 0.0]),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn jump_rejected_then_track_resumed() {
+        let limits = MotionLimits {
+            max_speed: 3.0,
+            max_accel: 100.0,
+        };
+        let config = Config::default();
+        let mut state = State::default();
+        let last = fix(0.0, 1000);
+        let prev = fix(0.0, 900);
+        let (pos, outcome) = config.check(&limits, &state, &last, Some(&prev), &fix(0.2, 1100));
+        assert_eq!(outcome, Outcome::Accepted);
+        assert_eq!(pos.coords[0], 0.2);
+        // 30 m in 100 ms
+        let (pos, outcome) = config.check(&limits, &state, &last, Some(&prev), &fix(30.0, 1100));
+        assert!(matches!(outcome, Outcome::Rejected { .. }));
+        assert_eq!(pos.coords[0], 0.0);
+        state.reject(fix(30.0, 1100), config.resume_fixes);
+        state.reject(fix(30.1, 1200), config.resume_fixes);
+        let (pos, outcome) = config.check(&limits, &state, &last, Some(&prev), &fix(30.2, 1300));
+        assert_eq!(outcome, Outcome::Reinitialised);
+        assert_eq!(pos.coords[0], 30.2);
+        assert_eq!(state.rejected, 2);
+    }
+
+    #[test]
+    fn jump_clamped_to_max_speed() {
+        let limits = MotionLimits {
+            max_speed: 2.0,
+            max_accel: 100.0,
+        };
+        let config = Config {
+            action: Action::Clamp,
+            ..Config::default()
+        };
+        let state = State::default();
+        let (pos, outcome) = config.check(&limits, &state, &fix(0.0, 0), None, &fix(10.0, 1000));
+        assert!(matches!(outcome, Outcome::Clamped { .. }));
+        assert!((pos.coords[0] - 2.0).abs() < 1e-5);
+    }
+}
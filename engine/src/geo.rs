@@ -0,0 +1,161 @@
+//! Conversions between zone local coordinates and WGS84.
+//!
+//! Zone local frame is a metric, right-handed frame placed on the local
+//! tangent plane (ENU) at the reference point. Its x axis is rotated by
+//! `bearing` degrees clockwise from north, z axis points up.
+
+use crate::utils::Coords;
+use serde_derive::{Deserialize, Serialize};
+
+// WGS84 ellipsoid parameters
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+const WGS84_E2: f64 = WGS84_F * (2.0 - WGS84_F);
+
+/// Geodetic position, angles in degrees, altitude in meters above ellipsoid
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct Wgs84 {
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+}
+
+/// Geodetic anchor point of zone local frame
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct Reference {
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+    /// local x axis direction, degrees clockwise from north
+    pub bearing: f64,
+}
+
+/// Earth-centered, earth-fixed cartesian coordinates
+#[derive(Copy, Clone, Debug)]
+pub struct Ecef(pub [f64; 3]);
+
+/// Local east-north-up coordinates
+#[derive(Copy, Clone, Debug)]
+pub struct Enu(pub [f64; 3]);
+
+pub fn wgs84_to_ecef(pos: &Wgs84) -> Ecef {
+    let (lat, lon) = (pos.lat.to_radians(), pos.lon.to_radians());
+    let n = WGS84_A / (1.0 - WGS84_E2 * lat.sin().powi(2)).sqrt();
+    Ecef([
+        (n + pos.alt) * lat.cos() * lon.cos(),
+        (n + pos.alt) * lat.cos() * lon.sin(),
+        (n * (1.0 - WGS84_E2) + pos.alt) * lat.sin(),
+    ])
+}
+
+pub fn ecef_to_wgs84(ecef: &Ecef) -> Wgs84 {
+    let [x, y, z] = ecef.0;
+    let p = (x * x + y * y).sqrt();
+    let lon = y.atan2(x);
+    // fixed point iteration, converges to sub-millimeter in a few steps
+    let mut lat = z.atan2(p * (1.0 - WGS84_E2));
+    let mut alt = 0.0;
+    for _ in 0..5 {
+        let n = WGS84_A / (1.0 - WGS84_E2 * lat.sin().powi(2)).sqrt();
+        alt = p / lat.cos() - n;
+        lat = z.atan2(p * (1.0 - WGS84_E2 * n / (n + alt)));
+    }
+    Wgs84 {
+        lat: lat.to_degrees(),
+        lon: lon.to_degrees(),
+        alt,
+    }
+}
+
+impl Reference {
+    pub fn new(lat: f64, lon: f64, alt: f64, bearing: f64) -> Reference {
+        Reference {
+            lat,
+            lon,
+            alt,
+            bearing,
+        }
+    }
+
+    fn origin(&self) -> Wgs84 {
+        Wgs84 {
+            lat: self.lat,
+            lon: self.lon,
+            alt: self.alt,
+        }
+    }
+
+    pub fn ecef_to_enu(&self, ecef: &Ecef) -> Enu {
+        let o = wgs84_to_ecef(&self.origin()).0;
+        let d = [ecef.0[0] - o[0], ecef.0[1] - o[1], ecef.0[2] - o[2]];
+        let (lat, lon) = (self.lat.to_radians(), self.lon.to_radians());
+        let (slat, clat, slon, clon) = (lat.sin(), lat.cos(), lon.sin(), lon.cos());
+        Enu([
+            -slon * d[0] + clon * d[1],
+            -slat * clon * d[0] - slat * slon * d[1] + clat * d[2],
+            clat * clon * d[0] + clat * slon * d[1] + slat * d[2],
+        ])
+    }
+
+    pub fn enu_to_ecef(&self, enu: &Enu) -> Ecef {
+        let o = wgs84_to_ecef(&self.origin()).0;
+        let [e, n, u] = enu.0;
+        let (lat, lon) = (self.lat.to_radians(), self.lon.to_radians());
+        let (slat, clat, slon, clon) = (lat.sin(), lat.cos(), lon.sin(), lon.cos());
+        Ecef([
+            o[0] - slon * e - slat * clon * n + clat * clon * u,
+            o[1] + clon * e - slat * slon * n + clat * slon * u,
+            o[2] + clat * n + slat * u,
+        ])
+    }
+
+    pub fn local_to_enu(&self, local: &Coords) -> Enu {
+        let (s, c) = self.bearing.to_radians().sin_cos();
+        let (x, y) = (local[0] as f64, local[1] as f64);
+        Enu([x * s - y * c, x * c + y * s, local[2] as f64])
+    }
+
+    pub fn enu_to_local(&self, enu: &Enu) -> Coords {
+        let (s, c) = self.bearing.to_radians().sin_cos();
+        let [e, n, u] = enu.0;
+        Coords([(e * s + n * c) as f32, (n * s - e * c) as f32, u as f32])
+    }
+
+    pub fn to_wgs84(&self, local: &Coords) -> Wgs84 {
+        ecef_to_wgs84(&self.enu_to_ecef(&self.local_to_enu(local)))
+    }
+
+    pub fn to_local(&self, pos: &Wgs84) -> Coords {
+        self.enu_to_local(&self.ecef_to_enu(&wgs84_to_ecef(pos)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_wgs84_round_trip() {
+        let reference = Reference::new(52.2297, 21.0122, 100.0, 30.0);
+        let local = Coords([120.5, -43.25, 7.0]);
+        let geo = reference.to_wgs84(&local);
+        let back = reference.to_local(&geo);
+        for i in 0..3 {
+            assert!((local[i] - back[i]).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn bearing_orients_x_axis() {
+        // x axis pointing east, 100m along x moves east only
+        let reference = Reference::new(0.0, 0.0, 0.0, 90.0);
+        let geo = reference.to_wgs84(&Coords([100.0, 0.0, 0.0]));
+        assert!(geo.lat.abs() < 1e-9);
+        assert!((geo.lon - 100.0 / 111_319.49).abs() < 1e-6);
+        // x axis pointing north
+        let reference = Reference::new(0.0, 0.0, 0.0, 0.0);
+        let geo = reference.to_wgs84(&Coords([100.0, 0.0, 0.0]));
+        assert!(geo.lon.abs() < 1e-9);
+        assert!(geo.lat > 0.0);
+    }
+}
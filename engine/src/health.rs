@@ -0,0 +1,263 @@
+//! Anchor health from residuals of fixes using it.
+//!
+//! Every accepted fix leaves a residual per anchor range, measured minus
+//! expected distance. Consistent bias points to moved anchor, large spread
+//! to noisy one and no ranges for a while to missing one. Moved anchor
+//! position is estimated from recent fixes, as if tags were anchors.
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::tracker::{least_squares, Range};
+use crate::utils::{Coords, DevId, Timestamp};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// residuals averaged, older ones fade out
+    pub samples: usize,
+    /// residuals needed before anchor is judged
+    pub min_samples: usize,
+    /// highest mean residual of healthy anchor [m]
+    pub bias_limit: f32,
+    /// highest residual deviation of healthy anchor [m]
+    pub noise_limit: f32,
+    /// time without ranges after which anchor is missing [ms]
+    pub missing_timeout: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Healthy,
+    Biased,
+    Noisy,
+    Missing,
+}
+
+/// Residual statistics of single anchor
+#[derive(Serialize, Clone, Debug)]
+pub struct Report {
+    pub id: DevId,
+    pub status: Status,
+    /// mean residual [m]
+    pub bias: f32,
+    /// residual deviation [m]
+    pub noise: f32,
+    pub count: u64,
+    pub last_seen: Timestamp,
+}
+
+/// Change of anchor state found by new residual
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Finding {
+    Status(Status),
+    /// estimated displacement from configured position
+    Moved(Coords),
+}
+
+#[derive(Clone, Debug)]
+struct Stats {
+    count: u64,
+    mean: f32,
+    variance: f32,
+    last_seen: Timestamp,
+    status: Status,
+    moved_reported: bool,
+    /// recent fixes with measured distances, for position estimate
+    fixes: VecDeque<Range>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    config: Config,
+    anchors: BTreeMap<DevId, Stats>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            samples: 50,
+            min_samples: 20,
+            bias_limit: 0.3,
+            noise_limit: 0.5,
+            missing_timeout: 30_000,
+        }
+    }
+}
+
+fn distance(a: &Coords, b: &Coords) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+impl Stats {
+    fn new() -> Stats {
+        Stats {
+            count: 0,
+            mean: 0.0,
+            variance: 0.0,
+            last_seen: 0,
+            status: Status::Healthy,
+            moved_reported: false,
+            fixes: VecDeque::new(),
+        }
+    }
+
+    fn judge(&self, config: &Config) -> Status {
+        if self.count < config.min_samples as u64 {
+            Status::Healthy
+        } else if self.mean.abs() > config.bias_limit {
+            Status::Biased
+        } else if self.variance.sqrt() > config.noise_limit {
+            Status::Noisy
+        } else {
+            Status::Healthy
+        }
+    }
+}
+
+impl Monitor {
+    pub fn new(config: Config) -> Monitor {
+        Monitor {
+            config,
+            anchors: BTreeMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Start watching anchor registered at `timestamp`, so it's reported
+    /// missing even if it never ranges
+    pub fn watch(&mut self, id: DevId, timestamp: Timestamp) {
+        let stats = self.anchors.entry(id).or_insert_with(Stats::new);
+        stats.last_seen = stats.last_seen.max(timestamp);
+    }
+
+    /// Drop statistics of anchor, eg. placed again by operator
+    pub fn forget(&mut self, id: DevId) {
+        self.anchors.remove(&id);
+    }
+
+    pub fn status(&self, id: DevId) -> Option<Status> {
+        self.anchors.get(&id).map(|s| s.status)
+    }
+
+    pub fn report(&self) -> Vec<Report> {
+        self.anchors
+            .iter()
+            .map(|(&id, s)| Report {
+                id,
+                status: s.status,
+                bias: s.mean,
+                noise: s.variance.sqrt(),
+                count: s.count,
+                last_seen: s.last_seen,
+            })
+            .collect()
+    }
+
+    /// Take range of anchor at `anchor` used by fix at `fix`
+    pub fn record(
+        &mut self,
+        id: DevId,
+        anchor: &Coords,
+        fix: &Coords,
+        measured: f32,
+        timestamp: Timestamp,
+    ) -> Vec<Finding> {
+        let config = &self.config;
+        let stats = self.anchors.entry(id).or_insert_with(Stats::new);
+        let residual = measured - distance(anchor, fix);
+        stats.count += 1;
+        stats.last_seen = stats.last_seen.max(timestamp);
+        let alpha = 1.0 / stats.count.min(config.samples.max(1) as u64) as f32;
+        let diff = residual - stats.mean;
+        stats.mean += alpha * diff;
+        stats.variance = (1.0 - alpha) * (stats.variance + alpha * diff * diff);
+        stats.fixes.push_back(Range::new(*fix, measured));
+        if stats.fixes.len() > config.samples {
+            stats.fixes.pop_front();
+        }
+        let mut findings = Vec::new();
+        let status = stats.judge(config);
+        if status != stats.status {
+            stats.status = status;
+            findings.push(Finding::Status(status));
+            if status == Status::Healthy {
+                stats.moved_reported = false;
+            }
+        }
+        if status == Status::Biased && !stats.moved_reported {
+            let estimate = least_squares::solve(anchor, stats.fixes.make_contiguous());
+            let offset = Coords([
+                estimate[0] - anchor[0],
+                estimate[1] - anchor[1],
+                estimate[2] - anchor[2],
+            ]);
+            if distance(&estimate, anchor) > config.bias_limit {
+                stats.moved_reported = true;
+                findings.push(Finding::Moved(offset));
+            }
+        }
+        findings
+    }
+
+    /// Anchor ranged by fix, which can't judge its residual, eg. fingerprint
+    pub fn seen(&mut self, id: DevId, timestamp: Timestamp) -> Vec<Finding> {
+        let config = &self.config;
+        let stats = self.anchors.entry(id).or_insert_with(Stats::new);
+        stats.last_seen = stats.last_seen.max(timestamp);
+        if stats.status != Status::Missing {
+            return Vec::new();
+        }
+        stats.status = stats.judge(config);
+        vec![Finding::Status(stats.status)]
+    }
+
+    /// Anchors without ranges for too long, newly found ones are returned
+    pub fn check_missing(&mut self, now: Timestamp) -> Vec<DevId> {
+        let timeout = self.config.missing_timeout;
+        self.anchors
+            .iter_mut()
+            .filter(|(_, s)| s.status != Status::Missing)
+            .filter(|(_, s)| now.saturating_sub(s.last_seen) >= timeout)
+            .map(|(&id, s)| {
+                s.status = Status::Missing;
+                id
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moved_anchor_biased_with_offset() {
+        let mut monitor = Monitor::new(Config::default());
+        let configured = Coords([0.0, 0.0, 0.0]);
+        let real = Coords([0.0, 1.0, 0.0]);
+        let mut findings = Vec::new();
+        for i in 0..40 {
+            let angle = i as f32 * 0.7;
+            let fix = Coords([5.0 * angle.cos() + 5.0, 5.0 * angle.sin() + 5.0, 0.0]);
+            let measured = distance(&real, &fix);
+            findings.extend(monitor.record(7, &configured, &fix, measured, i * 100));
+        }
+        assert_eq!(monitor.status(7), Some(Status::Biased));
+        let offset = findings
+            .iter()
+            .find_map(|f| match f {
+                Finding::Moved(o) => Some(*o),
+                _ => None,
+            })
+            .unwrap();
+        assert!(offset[0].abs() < 0.1);
+        assert!((offset[1] - 1.0).abs() < 0.1);
+        assert_eq!(monitor.check_missing(40_000), vec![7]);
+        assert_eq!(monitor.status(7), Some(Status::Missing));
+    }
+}
@@ -0,0 +1,167 @@
+//! Trajectories of devices over configurable time span, eg. to review last
+//! hour of movement.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::utils::{Coords, DevId, Timestamp, Trace};
+
+pub struct History {
+    duration: Timestamp,
+    tracks: BTreeMap<DevId, VecDeque<Trace>>,
+}
+
+fn interpolate(a: &Trace, b: &Trace, timestamp: Timestamp) -> Trace {
+    let span = b.timestamp.saturating_sub(a.timestamp);
+    if span == 0 {
+        return Trace { timestamp, ..*b };
+    }
+    let k = timestamp.saturating_sub(a.timestamp) as f32 / span as f32;
+    let mut coords = Coords([0.0; 3]);
+    for i in 0..3 {
+        coords[i] = a.coords[i] + (b.coords[i] - a.coords[i]) * k;
+    }
+    Trace { coords, timestamp }
+}
+
+/// Keep at most one trace per `interval`, the last one is always kept
+pub fn downsample(traces: &[Trace], interval: Timestamp) -> Vec<Trace> {
+    let mut out: Vec<Trace> = Vec::new();
+    for (i, t) in traces.iter().enumerate() {
+        let keep = match out.last() {
+            Some(last) => t.timestamp >= last.timestamp + interval || i == traces.len() - 1,
+            None => true,
+        };
+        if keep {
+            out.push(*t);
+        }
+    }
+    out
+}
+
+impl History {
+    /// Store keeping `duration` [ms] of every trajectory
+    pub fn new(duration: Timestamp) -> History {
+        History {
+            duration,
+            tracks: BTreeMap::new(),
+        }
+    }
+
+    pub fn duration(&self) -> Timestamp {
+        self.duration
+    }
+
+    /// Add position, out of order ones are put in place
+    pub fn record(&mut self, id: DevId, trace: Trace) {
+        let track = self.tracks.entry(id).or_default();
+        let idx = track
+            .iter()
+            .rposition(|t| t.timestamp <= trace.timestamp)
+            .map_or(0, |i| i + 1);
+        if idx > 0 && track[idx - 1].timestamp == trace.timestamp {
+            track[idx - 1] = trace;
+        } else {
+            track.insert(idx, trace);
+        }
+        let newest = track.back().unwrap().timestamp;
+        while let Some(oldest) = track.front() {
+            if oldest.timestamp + self.duration >= newest {
+                break;
+            }
+            track.pop_front();
+        }
+    }
+
+    pub fn remove(&mut self, id: DevId) {
+        self.tracks.remove(&id);
+    }
+
+    pub fn devices(&self) -> Vec<DevId> {
+        self.tracks.keys().cloned().collect()
+    }
+
+    /// Positions of device recorded between `from` and `to`, inclusive
+    pub fn range(&self, id: DevId, from: Timestamp, to: Timestamp) -> Vec<Trace> {
+        match self.tracks.get(&id) {
+            Some(track) => {
+                let start = track.partition_point(|t| t.timestamp < from);
+                track
+                    .range(start..)
+                    .take_while(|t| t.timestamp <= to)
+                    .cloned()
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Position of device at given time, interpolated between recorded
+    /// ones, `None` outside of recorded span
+    pub fn at(&self, id: DevId, timestamp: Timestamp) -> Option<Trace> {
+        let track = self.tracks.get(&id)?;
+        let idx = track.partition_point(|t| t.timestamp < timestamp);
+        let next = track.get(idx)?;
+        if next.timestamp == timestamp {
+            return Some(*next);
+        }
+        if idx == 0 {
+            return None;
+        }
+        Some(interpolate(&track[idx - 1], next, timestamp))
+    }
+
+    /// Positions of every device known at given time
+    pub fn all_at(&self, timestamp: Timestamp) -> Vec<(DevId, Trace)> {
+        self.tracks
+            .keys()
+            .filter_map(|&id| self.at(id, timestamp).map(|t| (id, t)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(x: f32, timestamp: Timestamp) -> Trace {
+        Trace {
+            coords: Coords([x, 0.0, 0.0]),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn range_and_interpolation() {
+        let mut history = History::new(1000);
+        for i in 0..20 {
+            history.record(1, trace(i as f32, i * 100));
+        }
+        history.record(2, trace(5.0, 1500));
+        history.record(2, trace(7.0, 1700));
+        // older than duration are dropped
+        assert!(history.range(1, 0, 800).is_empty());
+        assert_eq!(history.range(1, 1000, 1300).len(), 4);
+        assert!((history.at(1, 1250).unwrap().coords[0] - 12.5).abs() < 1e-5);
+        assert!(history.at(1, 5000).is_none());
+        let all = history.all_at(1600);
+        assert_eq!(all.len(), 2);
+        assert!((all[1].1.coords[0] - 6.0).abs() < 1e-5);
+        let sparse = downsample(&history.range(1, 0, 2000), 300);
+        let ts: Vec<Timestamp> = sparse.iter().map(|t| t.timestamp).collect();
+        assert_eq!(ts, vec![900, 1200, 1500, 1800, 1900]);
+    }
+
+    #[test]
+    fn out_of_order_record() {
+        let mut history = History::new(1000);
+        history.record(1, trace(0.0, 0));
+        history.record(1, trace(2.0, 200));
+        history.record(1, trace(1.0, 100));
+        let xs: Vec<f32> = history
+            .range(1, 0, 200)
+            .iter()
+            .map(|t| t.coords[0])
+            .collect();
+        assert_eq!(xs, vec![0.0, 1.0, 2.0]);
+    }
+}
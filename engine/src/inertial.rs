@@ -0,0 +1,184 @@
+//! Fusion of tag inertial sensors with ranges.
+//!
+//! Steps taken in gyro heading since the last fix give displacement which
+//! predicts next position. Trackers start from the prediction and least
+//! squares fix is blended with it by their deviations. Without ranges for
+//! a while position follows steps alone. Gyro heading is relative to the
+//! tag, its offset to zone frame is learned from fixes.
+
+use serde_derive::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::utils::{Coords, Timestamp};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// deviation added by every step [m]
+    pub step_sigma: f32,
+    /// deviation of fix from ranges [m]
+    pub fix_sigma: f32,
+    /// time without fix after which steps move device alone [ms]
+    pub outage: Timestamp,
+    /// part of heading error corrected by every fix
+    pub calibration_gain: f32,
+    /// shortest movement between fixes used to correct heading [m]
+    pub calibration_distance: f32,
+}
+
+/// Displacement since last fix with its variance
+#[derive(Copy, Clone, Debug)]
+pub struct Motion {
+    pub displacement: [f32; 2],
+    pub variance: f32,
+    pub fix_variance: f32,
+}
+
+/// Inertial memory kept per device
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct State {
+    /// last gyro heading [rad]
+    heading: Option<f32>,
+    /// gyro heading to zone frame [rad]
+    offset: f32,
+    displacement: [f32; 2],
+    steps: u32,
+    /// time of the last fix from ranges
+    fix_timestamp: Timestamp,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            step_sigma: 0.1,
+            fix_sigma: 0.3,
+            outage: 1000,
+            calibration_gain: 0.3,
+            calibration_distance: 0.5,
+        }
+    }
+}
+
+fn wrap_angle(a: f32) -> f32 {
+    (a + PI).rem_euclid(2.0 * PI) - PI
+}
+
+impl Motion {
+    pub fn predict(&self, from: &Coords) -> Coords {
+        Coords([
+            from[0] + self.displacement[0],
+            from[1] + self.displacement[1],
+            from[2],
+        ])
+    }
+
+    /// Blend predicted position with fix, by their variances
+    pub fn fuse(&self, predicted: &Coords, fix: &Coords) -> Coords {
+        let k = self.variance / (self.variance + self.fix_variance);
+        Coords([
+            predicted[0] + k * (fix[0] - predicted[0]),
+            predicted[1] + k * (fix[1] - predicted[1]),
+            fix[2],
+        ])
+    }
+}
+
+impl State {
+    pub fn set_heading(&mut self, heading: f32) {
+        self.heading = Some(heading);
+    }
+
+    /// Heading offset learned so far [rad]
+    pub fn heading_offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Add step, ignored until heading is known
+    pub fn step(&mut self, length: f32) -> bool {
+        let heading = match self.heading {
+            Some(h) => h + self.offset,
+            None => return false,
+        };
+        self.displacement[0] += length * heading.cos();
+        self.displacement[1] += length * heading.sin();
+        self.steps += 1;
+        true
+    }
+
+    /// Motion since last fix, `None` without steps
+    pub fn pending(&self, config: &Config) -> Option<Motion> {
+        if self.steps == 0 {
+            return None;
+        }
+        Some(Motion {
+            displacement: self.displacement,
+            variance: self.steps as f32 * config.step_sigma.powi(2),
+            fix_variance: config.fix_sigma.powi(2),
+        })
+    }
+
+    /// Forget steps already applied to position
+    pub fn clear(&mut self) {
+        self.displacement = [0.0; 2];
+        self.steps = 0;
+    }
+
+    /// Steps were used by fix from ranges
+    pub fn fixed(&mut self, timestamp: Timestamp) {
+        self.clear();
+        self.fix_timestamp = timestamp;
+    }
+
+    /// Ranges are missing for longer than configured outage
+    pub fn in_outage(&self, config: &Config, timestamp: Timestamp) -> bool {
+        timestamp.saturating_sub(self.fix_timestamp) >= config.outage
+    }
+
+    /// Turn heading towards direction of movement between fixes, returns
+    /// new offset when it was corrected
+    pub fn calibrate(&mut self, config: &Config, moved: [f32; 2]) -> Option<f32> {
+        let walked = self.displacement[0].hypot(self.displacement[1]);
+        if self.steps == 0
+            || walked < config.calibration_distance
+            || moved[0].hypot(moved[1]) < config.calibration_distance
+        {
+            return None;
+        }
+        let error =
+            wrap_angle(moved[1].atan2(moved[0]) - self.displacement[1].atan2(self.displacement[0]));
+        self.offset = wrap_angle(self.offset + config.calibration_gain * error);
+        Some(self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_predict_and_heading_calibrates() {
+        let config = Config::default();
+        let mut state = State::default();
+        assert!(!state.step(0.7));
+        // gyro reference is 90 degrees off zone x axis
+        state.set_heading(0.0);
+        for _ in 0..4 {
+            assert!(state.step(0.5));
+        }
+        let motion = state.pending(&config).unwrap();
+        let predicted = motion.predict(&Coords([1.0, 1.0, 0.0]));
+        assert!((predicted[0] - 3.0).abs() < 1e-5);
+        let fused = motion.fuse(&predicted, &Coords([1.0, 3.0, 0.0]));
+        assert!(fused[0] < 3.0 && fused[1] > 1.0);
+        for _ in 0..20 {
+            state.calibrate(&config, [0.0, 2.0]);
+            let offset = state.heading_offset();
+            state.clear();
+            for _ in 0..4 {
+                state.step(0.5);
+            }
+            assert!(offset > 0.0);
+        }
+        assert!((state.heading_offset() - PI / 2.0).abs() < 0.01);
+    }
+}
@@ -1,5 +1,22 @@
+pub mod baro;
+pub mod clock;
 pub mod device;
+pub mod event;
+pub mod fingerprint;
+pub mod gating;
+pub mod geo;
+pub mod health;
+pub mod history;
+pub mod inertial;
+pub mod manager;
+pub mod map;
 pub mod measure;
+pub mod planner;
+pub mod site;
+pub mod smoothing;
+pub mod snapshot;
+pub mod stationary;
+pub mod tracker;
 pub mod utils;
 pub mod zone;
 
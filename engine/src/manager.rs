@@ -0,0 +1,576 @@
+//! Multiple zones handled together, eg. floors or halls of one site.
+//!
+//! Measures are routed to the zone owning measured devices, unless zone
+//! is selected explicitly. Devices unknown to every zone land in the
+//! default zone.
+//!
+//! Zones are placed in common site frame. Tag leaving boundary of its zone
+//! is handed over, together with its track, to overlapping neighbour zone.
+//! Anchors placed in overlap regions are shared by both zones.
+
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use crate::clock::{Clock, SystemClock};
+use crate::device;
+use crate::event::{Event, Subscriber};
+use crate::measure;
+use crate::utils::{Coords, DevId, Timestamp, ZoneId};
+use crate::zone::{ExitCode, LivenessEvent, Zone};
+
+/// Tag moved from one zone to another
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct Handover {
+    pub id: DevId,
+    pub from: ZoneId,
+    pub to: ZoneId,
+}
+
+pub struct ZoneManager {
+    zones: BTreeMap<ZoneId, Zone>,
+    default_zone: Option<ZoneId>,
+    handovers: Vec<Handover>,
+    clock: Arc<dyn Clock>,
+    subscribers: Vec<Arc<Mutex<Box<dyn Subscriber>>>>,
+}
+
+impl Default for ZoneManager {
+    fn default() -> ZoneManager {
+        ZoneManager {
+            zones: BTreeMap::new(),
+            default_zone: None,
+            handovers: Vec::new(),
+            clock: Arc::new(SystemClock),
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+impl ZoneManager {
+    pub fn new() -> ZoneManager {
+        ZoneManager::default()
+    }
+
+    /// Clock shared by all managed zones
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        for zone in self.zones.values_mut() {
+            zone.set_clock(clock.clone());
+        }
+        self.clock = clock;
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    /// Receive events of every zone, including ones added later, and
+    /// handovers between them
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        let shared = Arc::new(Mutex::new(subscriber));
+        for zone in self.zones.values_mut() {
+            zone.subscribe(Box::new(shared.clone()));
+        }
+        self.subscribers.push(shared);
+    }
+
+    /// Take zone under management, the first one becomes default. Zone
+    /// starts to use manager clock and notify manager subscribers.
+    pub fn add_zone(&mut self, mut zone: Zone) -> ExitCode {
+        if self.zones.contains_key(&zone.id) {
+            return ExitCode::AlreadyExist;
+        }
+        zone.set_clock(self.clock.clone());
+        for shared in self.subscribers.iter() {
+            zone.subscribe(Box::new(shared.clone()));
+        }
+        if self.default_zone.is_none() {
+            self.default_zone = Some(zone.id);
+        }
+        self.zones.insert(zone.id, zone);
+        ExitCode::Ok
+    }
+
+    pub fn remove_zone(&mut self, id: ZoneId) -> Option<Zone> {
+        let zone = self.zones.remove(&id)?;
+        if self.default_zone == Some(id) {
+            self.default_zone = self.zones.keys().next().cloned();
+        }
+        Some(zone)
+    }
+
+    pub fn zone(&self, id: ZoneId) -> Option<&Zone> {
+        self.zones.get(&id)
+    }
+
+    pub fn zone_mut(&mut self, id: ZoneId) -> Option<&mut Zone> {
+        self.zones.get_mut(&id)
+    }
+
+    pub fn zones(&self) -> impl Iterator<Item = &Zone> {
+        self.zones.values()
+    }
+
+    pub fn zone_ids(&self) -> Vec<ZoneId> {
+        self.zones.keys().cloned().collect()
+    }
+
+    pub fn set_default_zone(&mut self, id: ZoneId) -> ExitCode {
+        if !self.zones.contains_key(&id) {
+            return ExitCode::UnknownZone;
+        }
+        self.default_zone = Some(id);
+        ExitCode::Ok
+    }
+
+    pub fn default_zone(&self) -> Option<ZoneId> {
+        self.default_zone
+    }
+
+    /// Explicitly given zone or the default one
+    pub fn select_zone_mut(&mut self, id: Option<ZoneId>) -> Option<&mut Zone> {
+        let id = id.or(self.default_zone)?;
+        self.zones.get_mut(&id)
+    }
+
+    /// First zone containing given device
+    pub fn device_zone(&self, dev: DevId) -> Option<ZoneId> {
+        self.zones
+            .values()
+            .find(|z| z.contains_device(dev))
+            .map(|z| z.id)
+    }
+
+    pub fn device_zone_mut(&mut self, dev: DevId) -> Option<&mut Zone> {
+        self.zones.values_mut().find(|z| z.contains_device(dev))
+    }
+
+    /// Zone for measure between two devices: the one knowing both of them,
+    /// or the one knowing the only known device. Unknown devices land in the
+    /// default zone, devices known only by different zones can't be linked.
+    pub fn route(&self, id1: DevId, id2: DevId) -> Option<ZoneId> {
+        self.route_pending(id1, id2, &HashMap::new())
+    }
+
+    /// Routing aware of devices assigned to zones, but not created yet
+    fn route_pending(
+        &self,
+        id1: DevId,
+        id2: DevId,
+        pending: &HashMap<DevId, ZoneId>,
+    ) -> Option<ZoneId> {
+        let has = |z: &Zone, id: DevId| z.contains_device(id) || pending.get(&id) == Some(&z.id);
+        let known = |id: DevId| self.zones.values().any(|z| has(z, id));
+        let (known1, known2) = (known(id1), known(id2));
+        if !known1 && !known2 {
+            return self.default_zone;
+        }
+        self.zones
+            .values()
+            .find(|z| has(z, id1) && has(z, id2))
+            .or_else(|| {
+                self.zones
+                    .values()
+                    .find(|z| (has(z, id1) && !known2) || (has(z, id2) && !known1))
+            })
+            .map(|z| z.id)
+    }
+
+    fn resolve(
+        &self,
+        zone: Option<ZoneId>,
+        id1: DevId,
+        id2: DevId,
+        pending: &HashMap<DevId, ZoneId>,
+    ) -> Result<ZoneId, ExitCode> {
+        match zone {
+            Some(id) if self.zones.contains_key(&id) => Ok(id),
+            Some(_) => Err(ExitCode::UnknownZone),
+            None => match self.route_pending(id1, id2, pending) {
+                Some(id) => Ok(id),
+                None if self.zones.is_empty() => Err(ExitCode::UnknownZone),
+                None => Err(ExitCode::UnknownLink),
+            },
+        }
+    }
+
+    /// Zones whose boundaries overlap with given one in site frame
+    pub fn neighbours(&self, id: ZoneId) -> Vec<ZoneId> {
+        let boundary = match self.zones.get(&id).and_then(|z| z.site_boundary()) {
+            Some(b) => b,
+            None => return Vec::new(),
+        };
+        self.zones
+            .values()
+            .filter(|z| z.id != id)
+            .filter(|z| match z.site_boundary() {
+                Some(b) => b.intersects(&boundary),
+                None => false,
+            })
+            .map(|z| z.id)
+            .collect()
+    }
+
+    /// Add anchor given in site frame to every zone covering it, anchors
+    /// in overlap regions are shared
+    pub fn add_site_anchor(&mut self, id: DevId, pos: Coords) -> ExitCode {
+        let mut result = ExitCode::UnknownZone;
+        for zone in self.zones.values_mut() {
+            let local = zone.placement().from_site(&pos);
+            if zone.boundary().is_some() && zone.covers(&local) {
+                result = zone.add_anchor(id, local);
+                if result != ExitCode::Ok {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    /// Move device with its track to another zone, position is transformed
+    /// into frame of the new zone
+    pub fn hand_over(&mut self, id: DevId, from: ZoneId, to: ZoneId) -> ExitCode {
+        let from_placement = match self.zones.get(&from) {
+            Some(z) => *z.placement(),
+            None => return ExitCode::UnknownZone,
+        };
+        match self.zones.get(&to) {
+            Some(z) if z.contains_device(id) => return ExitCode::AlreadyExist,
+            Some(_) => (),
+            None => return ExitCode::UnknownZone,
+        }
+        let mut dev = match self.zones.get_mut(&from).unwrap().take_device(id) {
+            Some(d) => d,
+            None => return ExitCode::UnknownDevice,
+        };
+        let zone = self.zones.get_mut(&to).unwrap();
+        let to_placement = *zone.placement();
+        dev.transform(&|c| to_placement.from_zone(&from_placement, c));
+        info!("Device {} handed over from zone {} to {}", id, from, to);
+        self.handovers.push(Handover { id, from, to });
+        for shared in self.subscribers.iter_mut() {
+            shared.notify(&Event::Handover { id, from, to });
+        }
+        zone.put_device(dev)
+    }
+
+    /// Hand tags which left boundary of their zone to neighbour covering them
+    fn check_handover(&mut self, ids: &[DevId]) {
+        for &id in ids.iter() {
+            let zone = match self.zones.values().find(|z| z.contains_device(id)) {
+                Some(z) if z.boundary().is_some() => z,
+                _ => continue,
+            };
+            let pos = match zone.get_device(id) {
+                Some(dev) if dev.role() == device::Role::Tag => {
+                    dev.estimate_position(dev.last_activity()).coords
+                }
+                _ => continue,
+            };
+            if zone.covers(&pos) {
+                continue;
+            }
+            let site = zone.placement().to_site(&pos);
+            let from = zone.id;
+            let to = self.neighbours(from).into_iter().find(|n| {
+                let z = &self.zones[n];
+                z.covers(&z.placement().from_site(&site))
+            });
+            if let Some(to) = to {
+                self.hand_over(id, from, to);
+            }
+        }
+    }
+
+    /// Handovers done since last call
+    pub fn take_handovers(&mut self) -> Vec<Handover> {
+        std::mem::take(&mut self.handovers)
+    }
+
+    pub fn add_measure(
+        &mut self,
+        zone: Option<ZoneId>,
+        id1: DevId,
+        id2: DevId,
+        dist: f32,
+        timestamp: Timestamp,
+        allow_dev_creation: bool,
+    ) -> ExitCode {
+        match self.resolve(zone, id1, id2, &HashMap::new()) {
+            Ok(id) => {
+                let zone = self.zones.get_mut(&id).unwrap();
+                let ret = zone.add_measure(id1, id2, dist, timestamp, allow_dev_creation);
+                self.check_handover(&[id1, id2]);
+                ret
+            }
+            Err(ret) => ret,
+        }
+    }
+
+    /// Inertial reading goes to the zone owning the tag
+    pub fn add_inertial(&mut self, zone: Option<ZoneId>, meas: &measure::Inertial) -> ExitCode {
+        let found = match zone {
+            Some(id) => self.zones.get_mut(&id),
+            None => self.device_zone_mut(meas.id()),
+        };
+        let ret = match found {
+            Some(z) => z.add_inertial(meas),
+            None => ExitCode::UnknownDevice,
+        };
+        self.check_handover(&[meas.id()]);
+        ret
+    }
+
+    /// Pressure reading goes to the zone owning the device
+    pub fn add_pressure(&mut self, zone: Option<ZoneId>, meas: &measure::Pressure) -> ExitCode {
+        let found = match zone {
+            Some(id) => self.zones.get_mut(&id),
+            None => self.device_zone_mut(meas.id),
+        };
+        match found {
+            Some(z) => z.add_pressure(meas),
+            None => ExitCode::UnknownDevice,
+        }
+    }
+
+    /// Signal strength goes to the zone owning the tag
+    pub fn add_rssi(&mut self, zone: Option<ZoneId>, meas: &measure::Rssi) -> ExitCode {
+        let found = match zone {
+            Some(id) => self.zones.get_mut(&id),
+            None => self.device_zone_mut(meas.id),
+        };
+        let ret = match found {
+            Some(z) => z.add_rssi(meas),
+            None => ExitCode::UnknownDevice,
+        };
+        self.check_handover(&[meas.id]);
+        ret
+    }
+
+    /// Split batch between zones, returns first failure
+    pub fn add_measures(
+        &mut self,
+        zone: Option<ZoneId>,
+        measures: &[measure::Distance],
+        allow_dev_creation: bool,
+    ) -> ExitCode {
+        let mut result = ExitCode::Ok;
+        let mut routed: BTreeMap<ZoneId, Vec<measure::Distance>> = BTreeMap::new();
+        // devices which will be created by this batch, keeps them in one zone
+        let mut pending: HashMap<DevId, ZoneId> = HashMap::new();
+        for m in measures.iter() {
+            match self.resolve(zone, m.id[0], m.id[1], &pending) {
+                Ok(id) => {
+                    for dev in m.id.iter() {
+                        if self.device_zone(*dev).is_none() {
+                            pending.entry(*dev).or_insert(id);
+                        }
+                    }
+                    routed.entry(id).or_default().push(*m);
+                }
+                Err(ret) => {
+                    if result == ExitCode::Ok {
+                        result = ret;
+                    }
+                }
+            }
+        }
+        for (id, batch) in routed.iter() {
+            let zone = self.zones.get_mut(id).unwrap();
+            let ret = zone.add_measures(batch, allow_dev_creation);
+            if result == ExitCode::Ok {
+                result = ret;
+            }
+        }
+        let mut ids: Vec<DevId> = measures.iter().flat_map(|m| m.id.iter().cloned()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        self.check_handover(&ids);
+        result
+    }
+
+    /// Newest measure timestamp seen by any zone
+    pub fn latest_timestamp(&self) -> Timestamp {
+        self.zones
+            .values()
+            .map(|z| z.latest_timestamp())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn get_dev_position(&self, id: DevId, timestamp: Timestamp) -> Option<device::Description> {
+        self.zones
+            .values()
+            .find_map(|z| z.get_dev_position(id, timestamp))
+    }
+
+    /// Positions of devices from every zone
+    pub fn get_all_devices_position(&mut self, timestamp: Timestamp) -> Vec<device::Description> {
+        self.zones
+            .values_mut()
+            .flat_map(|z| z.get_devices_position(timestamp, false))
+            .collect()
+    }
+
+    /// Positions of devices from every zone at current clock time
+    pub fn get_all_devices_position_now(&mut self) -> Vec<device::Description> {
+        self.zones
+            .values_mut()
+            .flat_map(|z| z.get_devices_position_now(false))
+            .collect()
+    }
+
+    pub fn check_liveness_now(&mut self) -> Vec<(ZoneId, LivenessEvent)> {
+        let now = self.now();
+        self.check_liveness(now)
+    }
+
+    pub fn check_liveness(&mut self, now: Timestamp) -> Vec<(ZoneId, LivenessEvent)> {
+        let mut events = Vec::new();
+        for zone in self.zones.values_mut() {
+            let id = zone.id;
+            events.extend(zone.check_liveness(now).into_iter().map(|e| (id, e)));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Polygon;
+    use crate::site::Placement;
+
+    fn manager() -> ZoneManager {
+        let mut manager = ZoneManager::new();
+        for (zone_id, offset) in [(1, 0.0), (2, 100.0)].iter() {
+            let mut zone = Zone::new(*zone_id);
+            for (i, pos) in [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]].iter().enumerate() {
+                let id = zone_id * 100 + i as DevId;
+                zone.add_anchor(id, Coords([pos[0] + offset, pos[1], 0.0]));
+            }
+            assert_eq!(manager.add_zone(zone), ExitCode::Ok);
+        }
+        manager
+    }
+
+    #[test]
+    fn measures_routed_by_anchor_membership() {
+        let mut manager = manager();
+        assert_eq!(manager.default_zone(), Some(1));
+        assert_eq!(
+            manager.add_measure(None, 7, 200, 5.0, 10, true),
+            ExitCode::Ok
+        );
+        assert_eq!(manager.device_zone(7), Some(2));
+        assert!(!manager.zone(1).unwrap().contains_device(7));
+        // unknown devices go to the default zone
+        assert_eq!(manager.add_measure(None, 8, 9, 1.0, 10, true), ExitCode::Ok);
+        assert_eq!(manager.device_zone(8), Some(1));
+        let ret = manager.add_measure(Some(5), 7, 200, 5.0, 20, true);
+        assert_eq!(ret, ExitCode::UnknownZone);
+        assert_eq!(manager.get_all_devices_position(20).len(), 9);
+        let desc = manager.get_dev_position(7, 20).unwrap();
+        assert_eq!(desc.zone, Some(2));
+    }
+
+    #[test]
+    fn batch_split_between_zones() {
+        let mut manager = manager();
+        let batch = vec![
+            measure::Distance::new([1, 100], 10, 5.0),
+            measure::Distance::new([1, 101], 10, 5.0),
+            measure::Distance::new([2, 200], 10, 5.0),
+        ];
+        assert_eq!(manager.add_measures(None, &batch, true), ExitCode::Ok);
+        assert_eq!(manager.device_zone(1), Some(1));
+        assert_eq!(manager.device_zone(2), Some(2));
+        assert_eq!(manager.add_zone(Zone::new(2)), ExitCode::AlreadyExist);
+        assert!(manager.remove_zone(1).is_some());
+        assert_eq!(manager.default_zone(), Some(2));
+    }
+
+    /// Custom subscriber, not a channel
+    struct Recorder(Arc<Mutex<Vec<Event>>>);
+
+    impl Subscriber for Recorder {
+        fn notify(&mut self, event: &Event) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn tag_handed_over_between_zones() {
+        let mut manager = ZoneManager::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        manager.subscribe(Box::new(Recorder(events.clone())));
+        // zone 1 covers site x in 0..12, zone 2 covers 10..22 and is rotated
+        let mut zone = Zone::new(1);
+        zone.set_boundary(Some(Polygon::new(vec![
+            [0.0, 0.0],
+            [12.0, 0.0],
+            [12.0, 10.0],
+            [0.0, 10.0],
+        ])));
+        manager.add_zone(zone);
+        let mut zone = Zone::new(2);
+        zone.set_placement(Placement::new(Coords([10.0, 0.0, 0.0]), 90.0));
+        zone.set_boundary(Some(Polygon::new(vec![
+            [0.0, 0.0],
+            [10.0, 0.0],
+            [10.0, -12.0],
+            [0.0, -12.0],
+        ])));
+        manager.add_zone(zone);
+        assert_eq!(manager.neighbours(1), vec![2]);
+        let anchors = [
+            (100, [0.5, 0.5]),
+            (101, [0.5, 9.5]),
+            (102, [11.0, 0.5]),
+            (103, [11.0, 9.5]),
+            (104, [21.5, 0.5]),
+            (105, [21.5, 9.5]),
+        ];
+        for (id, pos) in anchors.iter() {
+            let ret = manager.add_site_anchor(*id, Coords([pos[0], pos[1], 0.0]));
+            assert_eq!(ret, ExitCode::Ok);
+        }
+        // anchors in overlap region are known by both zones
+        assert!(manager.zone(1).unwrap().contains_device(102));
+        assert!(manager.zone(2).unwrap().contains_device(102));
+
+        let tag = 1;
+        for step in 0..14 {
+            let x = 5.0 + step as f32;
+            let batch: Vec<measure::Distance> = anchors
+                .iter()
+                .map(|(id, a)| {
+                    let d = ((a[0] - x).powi(2) + (a[1] - 5.0_f32).powi(2)).sqrt();
+                    measure::Distance::new([tag, *id], step * 100, d)
+                })
+                .collect();
+            manager.add_measures(None, &batch, true);
+        }
+        let handovers = manager.take_handovers();
+        assert_eq!(
+            handovers,
+            vec![Handover {
+                id: tag,
+                from: 1,
+                to: 2
+            }]
+        );
+        assert_eq!(manager.device_zone(tag), Some(2));
+        let handover = Event::Handover {
+            id: tag,
+            from: 1,
+            to: 2,
+        };
+        assert!(events.lock().unwrap().contains(&handover));
+        let zone = manager.zone(2).unwrap();
+        let desc = zone.get_dev_position(tag, 1300).unwrap();
+        let site = zone.placement().to_site(&desc.pos.coords);
+        assert!((site[0] - 18.0).abs() < 0.1 && (site[1] - 5.0).abs() < 0.1);
+    }
+}
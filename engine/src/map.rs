@@ -0,0 +1,251 @@
+//! Floor plan obstacles used to keep tracks physically possible.
+//!
+//! Map is two dimensional, only x and y coordinates are constrained and z is
+//! passed through unchanged.
+
+use crate::utils::Coords;
+use serde_derive::{Deserialize, Serialize};
+
+/// Distance kept between constrained position and obstacle
+const WALL_MARGIN: f32 = 0.05;
+/// Limit of snapping iterations when blocked areas touch each other
+const SNAP_ITERATIONS: usize = 4;
+
+/// Wall of no thickness between two points
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Segment {
+    pub from: [f32; 2],
+    pub to: [f32; 2],
+}
+
+/// Area where device can't be placed, eg. shelving
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Polygon {
+    pub points: Vec<[f32; 2]>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ObstacleMap {
+    #[serde(default)]
+    pub walls: Vec<Segment>,
+    #[serde(default)]
+    pub blocked: Vec<Polygon>,
+}
+
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn cross(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn dot(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+/// Parameter `t` along `p -> q` where it intersects `a -> b`, if it does
+fn intersection(p: [f32; 2], q: [f32; 2], a: [f32; 2], b: [f32; 2]) -> Option<f32> {
+    let r = sub(q, p);
+    let s = sub(b, a);
+    let denom = cross(r, s);
+    if denom.abs() < f32::EPSILON {
+        // parallel or collinear, sliding along a wall is allowed
+        return None;
+    }
+    let ap = sub(a, p);
+    let t = cross(ap, s) / denom;
+    let u = cross(ap, r) / denom;
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+fn closest_on_segment(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    let ab = sub(b, a);
+    let len2 = dot(ab, ab);
+    if len2 == 0.0 {
+        return a;
+    }
+    let t = (dot(sub(p, a), ab) / len2).clamp(0.0, 1.0);
+    [a[0] + ab[0] * t, a[1] + ab[1] * t]
+}
+
+impl Segment {
+    pub fn new(from: [f32; 2], to: [f32; 2]) -> Segment {
+        Segment { from, to }
+    }
+}
+
+impl Polygon {
+    pub fn new(points: Vec<[f32; 2]>) -> Polygon {
+        Polygon { points }
+    }
+
+    fn edges(&self) -> impl Iterator<Item = ([f32; 2], [f32; 2])> + '_ {
+        let n = self.points.len();
+        (0..n).map(move |i| (self.points[i], self.points[(i + 1) % n]))
+    }
+
+    /// Ray casting point in polygon test
+    pub fn contains(&self, p: [f32; 2]) -> bool {
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if (a[1] > p[1]) != (b[1] > p[1]) {
+                let x = a[0] + (p[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+                if p[0] < x {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+
+    /// True when polygons share some area
+    pub fn intersects(&self, other: &Polygon) -> bool {
+        self.points.iter().any(|p| other.contains(*p))
+            || other.points.iter().any(|p| self.contains(*p))
+            || self.edges().any(|(a, b)| {
+                other
+                    .edges()
+                    .any(|(c, d)| intersection(a, b, c, d).is_some())
+            })
+    }
+
+    /// Move point lying inside polygon just behind its nearest edge
+    fn push_out(&self, p: [f32; 2]) -> [f32; 2] {
+        let mut best = p;
+        let mut best_dist = f32::MAX;
+        for (a, b) in self.edges() {
+            let c = closest_on_segment(p, a, b);
+            let d = sub(c, p);
+            let dist = dot(d, d);
+            if dist < best_dist {
+                best_dist = dist;
+                best = c;
+            }
+        }
+        let dist = best_dist.sqrt();
+        if dist < f32::EPSILON {
+            return best;
+        }
+        let dir = sub(best, p);
+        [
+            best[0] + dir[0] / dist * WALL_MARGIN,
+            best[1] + dir[1] / dist * WALL_MARGIN,
+        ]
+    }
+}
+
+impl ObstacleMap {
+    pub fn new() -> ObstacleMap {
+        ObstacleMap::default()
+    }
+
+    pub fn from_json(txt: &str) -> Result<ObstacleMap, serde_json::Error> {
+        serde_json::from_str(txt)
+    }
+
+    pub fn is_blocked(&self, p: &Coords) -> bool {
+        self.blocked.iter().any(|b| b.contains([p[0], p[1]]))
+    }
+
+    /// Smallest parameter `t` along `from -> to` hitting a wall or blocked area edge
+    pub fn first_crossing(&self, from: &Coords, to: &Coords) -> Option<f32> {
+        let p = [from[0], from[1]];
+        let q = [to[0], to[1]];
+        let walls = self.walls.iter().map(|w| (w.from, w.to));
+        let edges = self.blocked.iter().flat_map(|b| b.edges());
+        walls
+            .chain(edges)
+            .filter_map(|(a, b)| intersection(p, q, a, b))
+            .fold(None, |acc: Option<f32>, t| match acc {
+                Some(v) if v <= t => Some(v),
+                _ => Some(t),
+            })
+    }
+
+    /// Move position out of blocked areas
+    pub fn snap_out(&self, pos: &Coords) -> Coords {
+        let mut p = [pos[0], pos[1]];
+        for _ in 0..SNAP_ITERATIONS {
+            match self.blocked.iter().find(|b| b.contains(p)) {
+                Some(b) => p = b.push_out(p),
+                None => break,
+            }
+        }
+        Coords([p[0], p[1], pos[2]])
+    }
+
+    /// Correct `next` fix so that track from `prev` does not pass through
+    /// obstacles and does not end inside a blocked area
+    pub fn constrain(&self, prev: &Coords, next: &Coords) -> Coords {
+        let next = self.snap_out(next);
+        if self.is_blocked(prev) {
+            return next;
+        }
+        match self.first_crossing(prev, &next) {
+            Some(t) => {
+                let dx = next[0] - prev[0];
+                let dy = next[1] - prev[1];
+                let len = (dx * dx + dy * dy).sqrt();
+                let t = (t - WALL_MARGIN / len.max(f32::EPSILON)).max(0.0);
+                Coords([prev[0] + dx * t, prev[1] + dy * t, next[2]])
+            }
+            None => next,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32, y: f32, size: f32) -> Polygon {
+        Polygon::new(vec![
+            [x, y],
+            [x + size, y],
+            [x + size, y + size],
+            [x, y + size],
+        ])
+    }
+
+    #[test]
+    fn track_stops_before_wall() {
+        let mut map = ObstacleMap::new();
+        map.walls.push(Segment::new([5.0, -10.0], [5.0, 10.0]));
+        let pos = map.constrain(&Coords([0.0, 0.0, 1.0]), &Coords([10.0, 0.0, 2.0]));
+        assert!(pos[0] < 5.0 && pos[0] > 4.9);
+        assert_eq!(pos[1], 0.0);
+        assert_eq!(pos[2], 2.0);
+        // movement along the wall side is not affected
+        let pos = map.constrain(&Coords([0.0, 0.0, 0.0]), &Coords([4.0, 5.0, 0.0]));
+        assert_eq!(pos[0], 4.0);
+        assert_eq!(pos[1], 5.0);
+    }
+
+    #[test]
+    fn position_snapped_out_of_blocked_area() {
+        let mut map = ObstacleMap::new();
+        map.blocked.push(square(0.0, 0.0, 4.0));
+        assert!(map.is_blocked(&Coords([3.5, 2.0, 0.0])));
+        let pos = map.snap_out(&Coords([3.5, 2.0, 0.0]));
+        assert!(!map.is_blocked(&pos));
+        assert!((pos[0] - 4.0 - WALL_MARGIN).abs() < 1e-4);
+        assert!((pos[1] - 2.0).abs() < 1e-4);
+        // track coming from the left stops at blocked area edge
+        let pos = map.constrain(&Coords([-3.0, 1.0, 0.0]), &Coords([0.5, 1.0, 0.0]));
+        assert!(pos[0] < 0.0 && !map.is_blocked(&pos));
+    }
+
+    #[test]
+    fn map_from_json() {
+        let txt = r#"{"walls": [{"from": [0, 0], "to": [0, 1]}],
+            "blocked": [{"points": [[1, 1], [2, 1], [2, 2]]}]}"#;
+        let map = ObstacleMap::from_json(txt).unwrap();
+        assert_eq!(map.walls.len(), 1);
+        assert_eq!(map.blocked[0].points.len(), 3);
+    }
+}
@@ -2,17 +2,54 @@ use crate::utils::{DevId, Timestamp};
 use serde_derive::{Deserialize, Serialize};
 use std::cmp::{max, min};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
 pub struct Distance {
     pub id: [DevId; 2],
     pub timestamp: Timestamp,
     pub distance: f32,
 }
 
+/// Reading of tag inertial sensors
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Inertial {
+    /// step detected by pedometer, its length [m]
+    Step {
+        id: DevId,
+        timestamp: Timestamp,
+        length: f32,
+    },
+    /// heading integrated by gyro [rad], counter clockwise from tag own
+    /// reference direction
+    Heading {
+        id: DevId,
+        timestamp: Timestamp,
+        heading: f32,
+    },
+}
+
+/// Barometric pressure [Pa] read by tag or reference anchor
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct Pressure {
+    pub id: DevId,
+    pub timestamp: Timestamp,
+    pub pressure: f32,
+}
+
+/// Signal strength [dBm] of anchor received by tag
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct Rssi {
+    pub id: DevId,
+    pub anchor: DevId,
+    pub timestamp: Timestamp,
+    pub rssi: f32,
+}
+
 const MEASURE_DEPTH: usize = 5;
+#[derive(Serialize, Deserialize, Clone)]
 pub struct List {
     dev: [DevId; 2],
-    measures_ts: [u32; MEASURE_DEPTH],
+    measures_ts: [Timestamp; MEASURE_DEPTH],
     measures_val: [f32; MEASURE_DEPTH],
 }
 
@@ -20,7 +57,7 @@ fn array_insert_pop<T>(arr: &mut [T; MEASURE_DEPTH], new_val: T) -> &[T; MEASURE
 where
     T: Copy,
 {
-    for i in 1..MEASURE_DEPTH {
+    for i in (1..MEASURE_DEPTH).rev() {
         arr[i] = arr[i - 1];
     }
     arr[0] = new_val;
@@ -37,6 +74,20 @@ impl Distance {
     }
 }
 
+impl Inertial {
+    pub fn id(&self) -> DevId {
+        match *self {
+            Inertial::Step { id, .. } | Inertial::Heading { id, .. } => id,
+        }
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        match *self {
+            Inertial::Step { timestamp, .. } | Inertial::Heading { timestamp, .. } => timestamp,
+        }
+    }
+}
+
 impl List {
     pub fn new(meas: Distance) -> List {
         let lo = min(meas.id[0], meas.id[1]);
@@ -60,7 +111,21 @@ impl List {
         array_insert_pop(&mut self.measures_ts, meas.timestamp);
     }
 
-    pub fn estimate(&self, _timestamp: u32) -> f32 {
-        *self.measures_val.last().unwrap()
+    pub fn estimate(&self, _timestamp: Timestamp) -> f32 {
+        self.measures_val[0]
+    }
+
+    /// Time of the newest measure
+    pub fn last_timestamp(&self) -> Timestamp {
+        self.measures_ts[0]
+    }
+
+    /// Id of the other side of the link
+    pub fn other(&self, id: DevId) -> DevId {
+        if self.dev[0] == id {
+            self.dev[1]
+        } else {
+            self.dev[0]
+        }
     }
 }
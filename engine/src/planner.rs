@@ -0,0 +1,318 @@
+//! Anchor layout evaluation before installation.
+//!
+//! Area is sampled with regular grid. Every point gets number of anchors in
+//! range and horizontal dilution of precision of ranges to them, expected
+//! error is GDOP times range deviation. Layout can be improved by the anchor
+//! which lowers worst GDOP over the area most.
+
+use nalgebra::Matrix2;
+use rayon::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Write;
+
+use crate::tracker::least_squares::MIN_RANGES;
+use crate::utils::Coords;
+
+/// Rectangle sampled every `step` metres at tag `height`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Area {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    pub step: f32,
+    #[serde(default)]
+    pub height: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// farthest anchor still ranged [m]
+    pub range_limit: f32,
+    /// range measurement deviation [m]
+    pub range_sigma: f32,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct Cell {
+    pub x: f32,
+    pub y: f32,
+    /// anchors in range
+    pub coverage: usize,
+    /// `None` where position can't be solved
+    pub gdop: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Grid {
+    pub cols: usize,
+    pub rows: usize,
+    /// row by row, starting at area minimum
+    pub cells: Vec<Cell>,
+}
+
+/// Anchor suggested to add with worst GDOP it leaves
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct Suggestion {
+    pub pos: Coords,
+    pub worst_gdop: Option<f32>,
+    pub uncovered: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// grid step not positive or not finite
+    Step(f32),
+    /// bounds not finite or minimum above maximum
+    Bounds,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Step(step) => write!(f, "grid step {} should be positive", step),
+            Error::Bounds => write!(f, "bounds should be finite, minimum first"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            range_limit: 30.0,
+            range_sigma: 0.1,
+        }
+    }
+}
+
+impl Area {
+    /// Step should be positive, bounds finite with minimum first
+    pub fn check(&self) -> Result<(), Error> {
+        if !self.step.is_finite() || self.step <= 0.0 {
+            return Err(Error::Step(self.step));
+        }
+        let finite = self
+            .min
+            .iter()
+            .chain(self.max.iter())
+            .all(|v| v.is_finite());
+        if !finite
+            || !self.height.is_finite()
+            || self.min[0] > self.max[0]
+            || self.min[1] > self.max[1]
+        {
+            return Err(Error::Bounds);
+        }
+        Ok(())
+    }
+
+    /// Grid of checked area
+    fn points(&self) -> (usize, usize, Vec<[f32; 2]>) {
+        let step = self.step;
+        let cols = ((self.max[0] - self.min[0]) / step).floor() as usize + 1;
+        let rows = ((self.max[1] - self.min[1]) / step).floor() as usize + 1;
+        let mut points = Vec::with_capacity(cols * rows);
+        for r in 0..rows {
+            for c in 0..cols {
+                points.push([self.min[0] + c as f32 * step, self.min[1] + r as f32 * step]);
+            }
+        }
+        (cols, rows, points)
+    }
+}
+
+fn evaluate_point(anchors: &[Coords], config: &Config, pos: &Coords) -> Cell {
+    let mut h = Matrix2::<f32>::zeros();
+    let mut coverage = 0;
+    for a in anchors.iter() {
+        let d = [pos[0] - a[0], pos[1] - a[1], pos[2] - a[2]];
+        let dist = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        if dist > config.range_limit {
+            continue;
+        }
+        coverage += 1;
+        if dist > f32::EPSILON {
+            let j = nalgebra::Vector2::new(d[0] / dist, d[1] / dist);
+            h += j * j.transpose();
+        }
+    }
+    let gdop = if coverage >= MIN_RANGES {
+        h.try_inverse()
+            .map(|inv| inv.trace().sqrt())
+            .filter(|g| g.is_finite() && *g > 0.0)
+    } else {
+        None
+    };
+    Cell {
+        x: pos[0],
+        y: pos[1],
+        coverage,
+        gdop,
+    }
+}
+
+/// GDOP and coverage of every grid point
+pub fn evaluate(anchors: &[Coords], area: &Area, config: &Config) -> Result<Grid, Error> {
+    area.check()?;
+    Ok(evaluate_area(anchors, area, config))
+}
+
+fn evaluate_area(anchors: &[Coords], area: &Area, config: &Config) -> Grid {
+    let (cols, rows, points) = area.points();
+    let cells = points
+        .iter()
+        .map(|p| evaluate_point(anchors, config, &Coords([p[0], p[1], area.height])))
+        .collect();
+    Grid { cols, rows, cells }
+}
+
+impl Grid {
+    /// Points where position can't be solved
+    pub fn uncovered(&self) -> usize {
+        self.cells.iter().filter(|c| c.gdop.is_none()).count()
+    }
+
+    /// Worst GDOP over solvable points
+    pub fn worst_gdop(&self) -> Option<f32> {
+        self.cells
+            .iter()
+            .filter_map(|c| c.gdop)
+            .fold(None, |w, g| Some(w.map_or(g, |w: f32| w.max(g))))
+    }
+
+    /// One line per point: x, y, coverage, gdop, expected error [m]
+    pub fn to_csv(&self, config: &Config) -> String {
+        let mut csv = String::from("x,y,coverage,gdop,error\n");
+        for c in self.cells.iter() {
+            let (gdop, error) = match c.gdop {
+                Some(g) => (g.to_string(), (g * config.range_sigma).to_string()),
+                None => (String::new(), String::new()),
+            };
+            writeln!(csv, "{},{},{},{},{}", c.x, c.y, c.coverage, gdop, error).unwrap();
+        }
+        csv
+    }
+
+    /// Binary greymap, black where unsolvable, brighter for lower GDOP,
+    /// white for GDOP of 1 and less, black at `max_gdop`
+    pub fn to_pgm(&self, max_gdop: f32) -> Vec<u8> {
+        let mut image = format!("P5\n{} {}\n255\n", self.cols, self.rows).into_bytes();
+        // image rows go from top, grid rows from area minimum
+        for r in (0..self.rows).rev() {
+            for c in self.cells[r * self.cols..(r + 1) * self.cols].iter() {
+                let value = match c.gdop {
+                    Some(g) => {
+                        let k = ((max_gdop - g) / (max_gdop - 1.0).max(f32::EPSILON)).max(0.0);
+                        (k.min(1.0) * 255.0) as u8
+                    }
+                    None => 0,
+                };
+                image.push(value);
+            }
+        }
+        image
+    }
+}
+
+/// Grid point, at height of existing anchors, where new anchor leaves the
+/// least unsolvable points and then the lowest worst GDOP
+pub fn suggest_anchor(
+    anchors: &[Coords],
+    area: &Area,
+    config: &Config,
+) -> Result<Option<Suggestion>, Error> {
+    area.check()?;
+    let height = match anchors.len() {
+        0 => area.height,
+        n => anchors.iter().map(|a| a[2]).sum::<f32>() / n as f32,
+    };
+    let (_, _, points) = area.points();
+    let best = points
+        .par_iter()
+        .map(|p| {
+            let pos = Coords([p[0], p[1], height]);
+            let mut layout = anchors.to_vec();
+            layout.push(pos);
+            let grid = evaluate_area(&layout, area, config);
+            Suggestion {
+                pos,
+                worst_gdop: grid.worst_gdop(),
+                uncovered: grid.uncovered(),
+            }
+        })
+        .min_by(|a, b| {
+            let worst = |s: &Suggestion| s.worst_gdop.unwrap_or(f32::INFINITY);
+            a.uncovered
+                .cmp(&b.uncovered)
+                .then(worst(a).partial_cmp(&worst(b)).unwrap())
+        });
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_corner_suggested() {
+        let anchors = [
+            Coords([0.0, 0.0, 0.0]),
+            Coords([10.0, 0.0, 0.0]),
+            Coords([0.0, 10.0, 0.0]),
+        ];
+        let area = Area {
+            min: [0.0, 0.0],
+            max: [10.0, 10.0],
+            step: 1.0,
+            height: 0.0,
+        };
+        let config = Config::default();
+        let grid = evaluate(&anchors, &area, &config).unwrap();
+        assert_eq!(grid.cells.len(), 121);
+        assert!(grid.cells.iter().all(|c| c.coverage == 3));
+        let center = grid.cells[5 * 11 + 5];
+        assert!(center.gdop.unwrap() < 1.5);
+        let worst = grid.worst_gdop().unwrap();
+        let suggestion = suggest_anchor(&anchors, &area, &config).unwrap().unwrap();
+        assert!(suggestion.worst_gdop.unwrap() < worst);
+        assert!(suggestion.pos[0] > 5.0 && suggestion.pos[1] > 5.0);
+        let pgm = grid.to_pgm(5.0);
+        assert!(pgm.starts_with(b"P5\n11 11\n255\n"));
+        assert_eq!(grid.to_csv(&config).lines().count(), 122);
+    }
+
+    #[test]
+    fn invalid_area_refused() {
+        let area = Area {
+            min: [0.0, 0.0],
+            max: [10.0, 10.0],
+            step: 0.0,
+            height: 0.0,
+        };
+        let config = Config::default();
+        assert_eq!(evaluate(&[], &area, &config).err(), Some(Error::Step(0.0)));
+        let nan = Area {
+            step: f32::NAN,
+            ..area.clone()
+        };
+        assert!(matches!(nan.check(), Err(Error::Step(_))));
+        let inverted = Area {
+            min: [10.0, 0.0],
+            max: [0.0, 10.0],
+            step: 1.0,
+            ..area.clone()
+        };
+        assert_eq!(inverted.check(), Err(Error::Bounds));
+        let infinite = Area {
+            max: [f32::INFINITY, 10.0],
+            step: 1.0,
+            ..area
+        };
+        assert_eq!(
+            suggest_anchor(&[], &infinite, &config).err(),
+            Some(Error::Bounds)
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Site frame shared by zones handled together.
+//!
+//! Every zone keeps positions in its own local frame. Placement maps it into
+//! site frame by rotation around z axis followed by translation.
+
+use crate::utils::Coords;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[serde(default)]
+pub struct Placement {
+    /// zone origin in site frame [m]
+    pub offset: Coords,
+    /// zone x axis direction, degrees counterclockwise from site x axis
+    pub rotation: f32,
+}
+
+impl Default for Placement {
+    fn default() -> Placement {
+        Placement {
+            offset: Coords([0.0; 3]),
+            rotation: 0.0,
+        }
+    }
+}
+
+impl Placement {
+    pub fn new(offset: Coords, rotation: f32) -> Placement {
+        Placement { offset, rotation }
+    }
+
+    pub fn to_site(&self, local: &Coords) -> Coords {
+        let (s, c) = self.rotation.to_radians().sin_cos();
+        Coords([
+            c * local[0] - s * local[1] + self.offset[0],
+            s * local[0] + c * local[1] + self.offset[1],
+            local[2] + self.offset[2],
+        ])
+    }
+
+    pub fn from_site(&self, site: &Coords) -> Coords {
+        let (s, c) = self.rotation.to_radians().sin_cos();
+        let (x, y) = (site[0] - self.offset[0], site[1] - self.offset[1]);
+        Coords([c * x + s * y, -s * x + c * y, site[2] - self.offset[2]])
+    }
+
+    /// Map coordinates given in frame of zone placed at `from` to this one
+    pub fn from_zone(&self, from: &Placement, local: &Coords) -> Coords {
+        self.from_site(&from.to_site(local))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn site_round_trip() {
+        let placement = Placement::new(Coords([10.0, 5.0, 3.0]), 90.0);
+        let site = placement.to_site(&Coords([1.0, 0.0, 0.0]));
+        assert!((site[0] - 10.0).abs() < 1e-5);
+        assert!((site[1] - 6.0).abs() < 1e-5);
+        assert!((site[2] - 3.0).abs() < 1e-5);
+        let local = placement.from_site(&site);
+        assert!((local[0] - 1.0).abs() < 1e-5);
+        assert!(local[1].abs() < 1e-5 && local[2].abs() < 1e-5);
+    }
+}
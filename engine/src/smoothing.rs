@@ -0,0 +1,303 @@
+//! Offline trajectory smoothing for post event analysis.
+//!
+//! Constant velocity Kalman filter runs forward over recorded fixes or
+//! ranges, then Rauch-Tung-Striebel pass runs backward, so every position
+//! benefits from measurements taken after it.
+
+use nalgebra::{Matrix6, Vector6};
+
+use crate::tracker::{least_squares, Range};
+use crate::utils::{Coords, Timestamp, Trace};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// white acceleration noise of motion model [m/s^2]
+    pub accel_sigma: f32,
+    /// deviation of position fixes, per axis [m]
+    pub fix_sigma: f32,
+    /// deviation of range measurements [m]
+    pub range_sigma: f32,
+}
+
+/// Recorded input of the filter
+#[derive(Clone, Debug)]
+pub enum Observation {
+    /// position calculated before, eg. from trajectory history
+    Fix(Coords),
+    /// raw ranges to anchors
+    Ranges(Vec<Range>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub timestamp: Timestamp,
+    pub observation: Observation,
+}
+
+#[derive(Clone, Debug)]
+pub struct Smoothed {
+    pub timestamp: Timestamp,
+    pub coords: Coords,
+    /// [m/s]
+    pub velocity: [f32; 3],
+    /// covariance of state `[x, y, z, vx, vy, vz]`
+    pub covariance: Matrix6<f32>,
+}
+
+/// Initial velocity deviation [m/s]
+const INITIAL_VELOCITY_SIGMA: f32 = 2.0;
+/// Initial position deviation when starting from ranges [m]
+const INITIAL_RANGES_SIGMA: f32 = 3.0;
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            accel_sigma: 1.0,
+            fix_sigma: 0.3,
+            range_sigma: 0.3,
+        }
+    }
+}
+
+impl Smoothed {
+    /// Position deviation along every axis [m]
+    pub fn position_sigma(&self) -> [f32; 3] {
+        [
+            self.covariance[(0, 0)].sqrt(),
+            self.covariance[(1, 1)].sqrt(),
+            self.covariance[(2, 2)].sqrt(),
+        ]
+    }
+}
+
+fn transition(dt: f32) -> Matrix6<f32> {
+    let mut f = Matrix6::identity();
+    for i in 0..3 {
+        f[(i, i + 3)] = dt;
+    }
+    f
+}
+
+fn process_noise(dt: f32, sigma: f32) -> Matrix6<f32> {
+    let q = sigma * sigma;
+    let mut m = Matrix6::zeros();
+    for i in 0..3 {
+        m[(i, i)] = dt.powi(4) / 4.0 * q;
+        m[(i, i + 3)] = dt.powi(3) / 2.0 * q;
+        m[(i + 3, i)] = dt.powi(3) / 2.0 * q;
+        m[(i + 3, i + 3)] = dt * dt * q;
+    }
+    m
+}
+
+/// Kalman update with single scalar measurement `z = h * x + noise`
+fn scalar_update(
+    x: &mut Vector6<f32>,
+    p: &mut Matrix6<f32>,
+    h: &Vector6<f32>,
+    innovation: f32,
+    variance: f32,
+) {
+    let ph = *p * h;
+    let s = h.dot(&ph) + variance;
+    if s <= 0.0 {
+        return;
+    }
+    let k = ph / s;
+    *x += k * innovation;
+    *p -= k * ph.transpose();
+}
+
+fn update(x: &mut Vector6<f32>, p: &mut Matrix6<f32>, obs: &Observation, config: &Config) {
+    match obs {
+        Observation::Fix(c) => {
+            for i in 0..3 {
+                let mut h = Vector6::zeros();
+                h[i] = 1.0;
+                let innovation = c[i] - x[i];
+                scalar_update(x, p, &h, innovation, config.fix_sigma.powi(2));
+            }
+        }
+        Observation::Ranges(ranges) => {
+            for r in ranges.iter() {
+                let d = [x[0] - r.from[0], x[1] - r.from[1], x[2] - r.from[2]];
+                let dist = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+                if dist < f32::EPSILON {
+                    continue;
+                }
+                let h = Vector6::new(d[0] / dist, d[1] / dist, d[2] / dist, 0.0, 0.0, 0.0);
+                let innovation = r.distance - dist;
+                scalar_update(x, p, &h, innovation, config.range_sigma.powi(2));
+            }
+        }
+    }
+}
+
+fn initial_state(obs: &Observation, config: &Config) -> (Vector6<f32>, Matrix6<f32>) {
+    let (pos, sigma) = match obs {
+        Observation::Fix(c) => (*c, config.fix_sigma),
+        Observation::Ranges(ranges) => {
+            let n = ranges.len().max(1) as f32;
+            let mut center = Coords([0.0; 3]);
+            for r in ranges.iter() {
+                for i in 0..3 {
+                    center[i] += r.from[i] / n;
+                }
+            }
+            (least_squares::solve(&center, ranges), INITIAL_RANGES_SIGMA)
+        }
+    };
+    let x = Vector6::new(pos[0], pos[1], pos[2], 0.0, 0.0, 0.0);
+    let mut p = Matrix6::zeros();
+    for i in 0..3 {
+        p[(i, i)] = sigma * sigma;
+        p[(i + 3, i + 3)] = INITIAL_VELOCITY_SIGMA.powi(2);
+    }
+    (x, p)
+}
+
+/// Smooth recorded samples, they have to be sorted by timestamp
+pub fn smooth(samples: &[Sample], config: &Config) -> Vec<Smoothed> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    let n = samples.len();
+    // forward pass, predicted values are kept for the backward one
+    let mut filtered: Vec<(Vector6<f32>, Matrix6<f32>)> = Vec::with_capacity(n);
+    let mut predicted: Vec<(Vector6<f32>, Matrix6<f32>, Matrix6<f32>)> = Vec::with_capacity(n);
+    let (mut x, mut p) = initial_state(&samples[0].observation, config);
+    update(&mut x, &mut p, &samples[0].observation, config);
+    filtered.push((x, p));
+    predicted.push((x, p, Matrix6::identity()));
+    for k in 1..n {
+        let dt = samples[k]
+            .timestamp
+            .saturating_sub(samples[k - 1].timestamp) as f32
+            / 1000.0;
+        let f = transition(dt);
+        let x_pred = f * x;
+        let p_pred = f * p * f.transpose() + process_noise(dt, config.accel_sigma);
+        predicted.push((x_pred, p_pred, f));
+        x = x_pred;
+        p = p_pred;
+        update(&mut x, &mut p, &samples[k].observation, config);
+        filtered.push((x, p));
+    }
+    // backward pass
+    let mut smoothed = filtered.clone();
+    for k in (0..n - 1).rev() {
+        let (x_f, p_f) = filtered[k];
+        let (x_pred, p_pred, f) = predicted[k + 1];
+        let inv = match p_pred.try_inverse() {
+            Some(inv) => inv,
+            None => continue,
+        };
+        let c = p_f * f.transpose() * inv;
+        let (x_next, p_next) = smoothed[k + 1];
+        let x_s = x_f + c * (x_next - x_pred);
+        let p_s = p_f + c * (p_next - p_pred) * c.transpose();
+        smoothed[k] = (x_s, p_s);
+    }
+    samples
+        .iter()
+        .zip(smoothed.iter())
+        .map(|(s, (x, p))| Smoothed {
+            timestamp: s.timestamp,
+            coords: Coords([x[0], x[1], x[2]]),
+            velocity: [x[3], x[4], x[5]],
+            covariance: *p,
+        })
+        .collect()
+}
+
+/// Smooth stored positions, eg. from trajectory history
+pub fn smooth_trajectory(traces: &[Trace], config: &Config) -> Vec<Smoothed> {
+    let samples: Vec<Sample> = traces
+        .iter()
+        .map(|t| Sample {
+            timestamp: t.timestamp,
+            observation: Observation::Fix(t.coords),
+        })
+        .collect();
+    smooth(&samples, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn truth(t: f32) -> [f32; 3] {
+        [1.0 + 0.5 * t, 2.0 + 0.2 * t, 0.0]
+    }
+
+    /// deterministic pseudo noise
+    fn noise(k: usize) -> f32 {
+        ((k as f32 * 12.9898).sin() * 43_758.547).fract().abs() * 0.6 - 0.3
+    }
+
+    fn error(coords: &Coords, t: f32) -> f32 {
+        let p = truth(t);
+        ((coords[0] - p[0]).powi(2) + (coords[1] - p[1]).powi(2)).sqrt()
+    }
+
+    #[test]
+    fn smoothing_reduces_fix_noise() {
+        let traces: Vec<Trace> = (0..50)
+            .map(|k| {
+                let t = k as f32 * 0.1;
+                let p = truth(t);
+                Trace {
+                    coords: Coords([p[0] + noise(2 * k), p[1] + noise(2 * k + 1), 0.0]),
+                    timestamp: k as Timestamp * 100,
+                }
+            })
+            .collect();
+        let config = Config {
+            accel_sigma: 0.2,
+            ..Config::default()
+        };
+        let smoothed = smooth_trajectory(&traces, &config);
+        assert_eq!(smoothed.len(), traces.len());
+        let raw: f32 = traces
+            .iter()
+            .enumerate()
+            .map(|(k, tr)| error(&tr.coords, k as f32 * 0.1))
+            .sum();
+        let smooth: f32 = smoothed
+            .iter()
+            .enumerate()
+            .map(|(k, s)| error(&s.coords, k as f32 * 0.1))
+            .sum();
+        assert!(smooth < raw * 0.5);
+        // middle of trajectory is known better than its start
+        assert!(smoothed[25].position_sigma()[0] < smoothed[0].position_sigma()[0]);
+        assert!((smoothed[25].velocity[0] - 0.5).abs() < 0.2);
+    }
+
+    #[test]
+    fn smoothing_from_ranges() {
+        let anchors = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0], [10.0, 10.0]];
+        let samples: Vec<Sample> = (0..30)
+            .map(|k| {
+                let t = k as f32 * 0.1;
+                let p = truth(t);
+                let ranges = anchors
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| {
+                        let d = ((a[0] - p[0]).powi(2) + (a[1] - p[1]).powi(2)).sqrt();
+                        Range::new(Coords([a[0], a[1], 0.0]), d + noise(4 * k + i) * 0.5)
+                    })
+                    .collect();
+                Sample {
+                    timestamp: k as Timestamp * 100,
+                    observation: Observation::Ranges(ranges),
+                }
+            })
+            .collect();
+        let smoothed = smooth(&samples, &Config::default());
+        for (k, s) in smoothed.iter().enumerate() {
+            assert!(error(&s.coords, k as f32 * 0.1) < 0.3);
+        }
+    }
+}
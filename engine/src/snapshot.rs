@@ -0,0 +1,273 @@
+//! Zone state saved to versioned document and restored from it, eg. to
+//! survive server restart without losing tracks.
+//!
+//! Document is available as JSON for inspection and as compact binary.
+//!
+//! Not everything is saved. Anchor health statistics, tag history,
+//! geofences tags are inside of, newest RSSI and reference pressure start
+//! empty after restore, subscribers and clock are set by the embedder
+//! again. Tags inside geofences are reported as entering them once more.
+
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::baro;
+use crate::device;
+use crate::event;
+use crate::fingerprint;
+use crate::gating;
+use crate::geo;
+use crate::health;
+use crate::inertial;
+use crate::map;
+use crate::measure;
+use crate::site;
+use crate::stationary;
+use crate::tracker;
+use crate::utils::{DevId, Timestamp, ZoneId};
+use crate::zone::ExitCode;
+
+/// Version of document layout, bumped on every incompatible change
+pub const VERSION: u32 = 1;
+
+/// Zone settings
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Config {
+    pub id: ZoneId,
+    pub geo_ref: Option<geo::Reference>,
+    pub map: Option<map::ObstacleMap>,
+    #[serde(with = "tracker_doc")]
+    pub tracker: tracker::Tracker,
+    pub liveness: device::LivenessTimeouts,
+    pub epoch_window: Option<Timestamp>,
+    pub parallel: bool,
+    pub placement: site::Placement,
+    pub boundary: Option<map::Polygon>,
+    pub geofences: Vec<event::Geofence>,
+    pub gating: Option<gating::Config>,
+    pub stationary: Option<stationary::Config>,
+    pub cooperative: Option<tracker::cooperative::Config>,
+    pub health: Option<health::Config>,
+    pub inertial: inertial::Config,
+    pub barometer: Option<baro::Config>,
+    pub fingerprint: fingerprint::Config,
+    pub fingerprints: Option<fingerprint::Database>,
+}
+
+/// Tracker is internally tagged in messages, which binary format can't
+/// decode, snapshot keeps it externally tagged
+mod tracker_doc {
+    use crate::tracker::{particle, Tracker};
+    use serde::{Deserializer, Serializer};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    enum Doc {
+        LeastSquares,
+        Particle(particle::Config),
+    }
+
+    pub fn serialize<S: Serializer>(tracker: &Tracker, s: S) -> Result<S::Ok, S::Error> {
+        let doc = match tracker {
+            Tracker::LeastSquares => Doc::LeastSquares,
+            Tracker::Particle(c) => Doc::Particle(c.clone()),
+        };
+        serde::Serialize::serialize(&doc, s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Tracker, D::Error> {
+        Ok(match <Doc as serde::Deserialize>::deserialize(d)? {
+            Doc::LeastSquares => Tracker::LeastSquares,
+            Doc::Particle(c) => Tracker::Particle(c),
+        })
+    }
+}
+
+/// Start of every document, read before the rest
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    /// always first, checked before the rest is decoded
+    pub version: u32,
+    pub config: Config,
+    /// devices with roles, metadata, recent positions and tracker state
+    pub devices: Vec<device::Data>,
+    /// measure history of every link
+    pub links: Vec<measure::List>,
+    /// measures of not finished epoch
+    pub epoch: Vec<(measure::Distance, bool)>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    /// document written by incompatible version
+    Version(u32),
+    /// setting refused by zone, named by its field
+    Config(&'static str),
+    /// device that can't be inserted, eg. listed twice
+    Device(DevId, ExitCode),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Json(e) => write!(f, "invalid JSON snapshot, {}", e),
+            Error::Binary(e) => write!(f, "invalid binary snapshot, {}", e),
+            Error::Version(v) => write!(f, "unsupported snapshot version {}", v),
+            Error::Config(field) => write!(f, "invalid {} in snapshot", field),
+            Error::Device(id, ret) => write!(f, "can't restore device {}, {:?}", id, ret),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn check_version(version: u32) -> Result<(), Error> {
+    if version == VERSION {
+        Ok(())
+    } else {
+        Err(Error::Version(version))
+    }
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(Error::Json)
+    }
+
+    pub fn from_json(txt: &str) -> Result<Snapshot, Error> {
+        let header: Header = serde_json::from_str(txt).map_err(Error::Json)?;
+        check_version(header.version)?;
+        serde_json::from_str(txt).map_err(Error::Json)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        bincode::serialize(self).map_err(Error::Binary)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, Error> {
+        let version: u32 = bincode::deserialize(bytes).map_err(Error::Binary)?;
+        check_version(version)?;
+        bincode::deserialize(bytes).map_err(Error::Binary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Coords;
+    use crate::zone::Zone;
+
+    const ANCHORS: [[f32; 2]; 4] = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0], [10.0, 10.0]];
+
+    fn feed(zone: &mut Zone, ts: Timestamp) {
+        let pos = [2.0 + ts as f32 / 1000.0, 3.0];
+        let batch: Vec<measure::Distance> = ANCHORS
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let d = ((a[0] - pos[0]).powi(2) + (a[1] - pos[1]).powi(2)).sqrt();
+                measure::Distance::new([1, 100 + i as DevId], ts, d)
+            })
+            .collect();
+        assert_eq!(zone.add_measures(&batch, true), ExitCode::Ok);
+    }
+
+    fn zone() -> Zone {
+        let mut zone = Zone::new(3);
+        let config = tracker::particle::Config {
+            particles: 50,
+            seed: Some(5),
+            ..Default::default()
+        };
+        zone.set_tracker(tracker::Tracker::Particle(config));
+        for (i, a) in ANCHORS.iter().enumerate() {
+            zone.add_anchor(100 + i as DevId, Coords([a[0], a[1], 0.0]));
+        }
+        for ts in 0..5 {
+            feed(&mut zone, ts * 100);
+        }
+        zone
+    }
+
+    fn assert_same_track(mut a: Zone, mut b: Zone) {
+        for ts in 5..10 {
+            feed(&mut a, ts * 100);
+            feed(&mut b, ts * 100);
+        }
+        let pa = a.get_dev_position(1, 1000).unwrap();
+        let pb = b.get_dev_position(1, 1000).unwrap();
+        assert_eq!(pa.pos.coords.0, pb.pos.coords.0);
+        assert_eq!(a.device_ids(), b.device_ids());
+    }
+
+    #[test]
+    fn json_round_trip_continues_track() {
+        let zone = zone();
+        let txt = zone.snapshot().to_json().unwrap();
+        let restored = Zone::restore(Snapshot::from_json(&txt).unwrap()).unwrap();
+        assert_eq!(restored.id, 3);
+        assert_same_track(zone, restored);
+    }
+
+    #[test]
+    fn binary_round_trip_continues_track() {
+        let zone = zone();
+        let bytes = zone.snapshot().to_bytes().unwrap();
+        let restored = Zone::restore(Snapshot::from_bytes(&bytes).unwrap()).unwrap();
+        assert_same_track(zone, restored);
+    }
+
+    #[test]
+    fn snapshot_size_bounded() {
+        let mut zone = zone();
+        let size = zone.snapshot().to_bytes().unwrap().len();
+        for ts in 5..200 {
+            feed(&mut zone, ts * 100);
+        }
+        assert_eq!(zone.snapshot().to_bytes().unwrap().len(), size);
+    }
+
+    #[test]
+    fn invalid_snapshot_refused() {
+        let mut snapshot = zone().snapshot();
+        snapshot.devices.push(snapshot.devices[0].clone());
+        match Zone::restore(snapshot) {
+            Err(Error::Device(id, ExitCode::AlreadyExist)) => assert_eq!(id, 100),
+            _ => panic!("duplicate device restored"),
+        }
+        let mut snapshot = zone().snapshot();
+        snapshot.config.tracker = tracker::Tracker::Particle(tracker::particle::Config {
+            particles: 0,
+            ..Default::default()
+        });
+        match Zone::restore(snapshot) {
+            Err(Error::Config(field)) => assert_eq!(field, "tracker"),
+            _ => panic!("invalid tracker restored"),
+        }
+    }
+
+    #[test]
+    fn unknown_version_rejected() {
+        let mut snapshot = Zone::new(1).snapshot();
+        snapshot.version = VERSION + 1;
+        let bytes = snapshot.to_bytes().unwrap();
+        match Snapshot::from_bytes(&bytes) {
+            Err(Error::Version(v)) => assert_eq!(v, VERSION + 1),
+            _ => panic!("version not checked"),
+        }
+        let mut doc: serde_json::Value =
+            serde_json::from_str(&Zone::new(1).snapshot().to_json().unwrap()).unwrap();
+        doc.as_object_mut().unwrap().remove("version");
+        match Snapshot::from_json(&doc.to_string()) {
+            Err(Error::Json(_)) => (),
+            _ => panic!("missing version accepted"),
+        }
+    }
+}
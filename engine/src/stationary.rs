@@ -0,0 +1,159 @@
+//! Detection of devices standing still.
+//!
+//! Parked tag jitters by measurement noise. When both its recent positions
+//! and ranges barely vary, position is frozen at their average until any of
+//! them leaves the noise band.
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::utils::{Coords, DevId};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// fixes needed to declare device stationary
+    pub window: usize,
+    /// highest position deviation of stationary device [m]
+    pub position_sigma: f32,
+    /// highest deviation of any range of stationary device [m]
+    pub range_sigma: f32,
+    /// position or range change which releases frozen device [m]
+    pub release_distance: f32,
+}
+
+/// Recent fixes and ranges of device, with position it is frozen at
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct State {
+    fixes: VecDeque<Coords>,
+    ranges: BTreeMap<DevId, VecDeque<f32>>,
+    frozen: Option<Coords>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            window: 10,
+            position_sigma: 0.1,
+            range_sigma: 0.1,
+            release_distance: 0.5,
+        }
+    }
+}
+
+fn mean(values: &VecDeque<f32>) -> f32 {
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+fn deviation(values: &VecDeque<f32>) -> f32 {
+    let m = mean(values);
+    (values.iter().map(|v| (v - m).powi(2)).sum::<f32>() / values.len() as f32).sqrt()
+}
+
+fn distance(a: &Coords, b: &Coords) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+impl State {
+    pub fn is_moving(&self) -> bool {
+        self.frozen.is_none()
+    }
+
+    fn mean_fix(&self) -> Coords {
+        let mut pos = Coords([0.0; 3]);
+        for fix in self.fixes.iter() {
+            for i in 0..3 {
+                pos[i] += fix[i] / self.fixes.len() as f32;
+            }
+        }
+        pos
+    }
+
+    fn moved(&self, config: &Config, coords: &Coords, ranges: &[(DevId, f32)]) -> bool {
+        let frozen = match &self.frozen {
+            Some(f) => f,
+            None => return false,
+        };
+        distance(frozen, coords) > config.release_distance
+            || ranges.iter().any(|(id, r)| match self.ranges.get(id) {
+                Some(v) if !v.is_empty() => (r - mean(v)).abs() > config.release_distance,
+                _ => false,
+            })
+    }
+
+    fn still(&self, config: &Config) -> bool {
+        if self.fixes.len() < config.window.max(2) {
+            return false;
+        }
+        let center = self.mean_fix();
+        let spread = (self
+            .fixes
+            .iter()
+            .map(|f| distance(f, &center).powi(2))
+            .sum::<f32>()
+            / self.fixes.len() as f32)
+            .sqrt();
+        spread <= config.position_sigma
+            && self
+                .ranges
+                .values()
+                .filter(|v| v.len() > 1)
+                .all(|v| deviation(v) <= config.range_sigma)
+    }
+
+    fn push(&mut self, window: usize, coords: Coords, ranges: &[(DevId, f32)]) {
+        self.fixes.push_back(coords);
+        if self.fixes.len() > window {
+            self.fixes.pop_front();
+        }
+        for &(id, r) in ranges.iter() {
+            let v = self.ranges.entry(id).or_default();
+            v.push_back(r);
+            if v.len() > window {
+                v.pop_front();
+            }
+        }
+    }
+
+    /// Take new fix with ranges it comes from, returns position to report
+    pub fn update(&mut self, config: &Config, coords: Coords, ranges: &[(DevId, f32)]) -> Coords {
+        if self.moved(config, &coords, ranges) {
+            // release at once, old statistics describe the parked position
+            *self = State::default();
+        }
+        self.push(config.window.max(2), coords, ranges);
+        if let Some(frozen) = self.frozen {
+            return frozen;
+        }
+        if self.still(config) {
+            let center = self.mean_fix();
+            self.frozen = Some(center);
+            return center;
+        }
+        coords
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_frozen_and_released_on_move() {
+        let config = Config::default();
+        let mut state = State::default();
+        let mut reported = Coords([0.0; 3]);
+        for i in 0..20 {
+            let jitter = if i % 2 == 0 { 0.05 } else { -0.05 };
+            let fix = Coords([5.0 + jitter, 5.0, 0.0]);
+            reported = state.update(&config, fix, &[(100, 7.0 + jitter)]);
+        }
+        assert!(!state.is_moving());
+        assert!((reported[0] - 5.0).abs() < 0.03);
+        let again = state.update(&config, Coords([5.05, 5.0, 0.0]), &[(100, 7.05)]);
+        assert_eq!(again, reported);
+        let moved = state.update(&config, Coords([6.0, 5.0, 0.0]), &[(100, 7.9)]);
+        assert!(state.is_moving());
+        assert_eq!(moved[0], 6.0);
+    }
+}
@@ -0,0 +1,136 @@
+//! Initial position of new device, found without any previous position.
+//!
+//! Iterative solvers started at the origin may converge to the mirror
+//! solution, so the first fix is searched globally: closed form over
+//! linearised range equations and a coarse grid over the anchors area,
+//! the better of them refined by least squares.
+
+use super::least_squares;
+use super::Range;
+use crate::utils::Coords;
+use nalgebra::{Matrix2, Vector2};
+
+/// Cells per side of the search grid
+const GRID_CELLS: usize = 40;
+const MIN_GRID_STEP: f32 = 0.1;
+/// Smallest anchor spread across their main axis [m], less is a line
+const MIN_SPREAD: f32 = 0.1;
+
+/// Horizontal distance squared, device assumed at height `z`
+fn flat_distance2(r: &Range, z: f32) -> f32 {
+    (r.distance.powi(2) - (r.from[2] - z).powi(2)).max(0.0)
+}
+
+/// Anchors span a plane, collinear ones leave mirror ambiguity
+fn spread_enough(ranges: &[Range]) -> bool {
+    let n = ranges.len() as f32;
+    let (mx, my) = ranges.iter().fold((0.0, 0.0), |(x, y), r| {
+        (x + r.from[0] / n, y + r.from[1] / n)
+    });
+    let mut cov = Matrix2::<f32>::zeros();
+    for r in ranges.iter() {
+        let d = Vector2::new(r.from[0] - mx, r.from[1] - my);
+        cov += d * d.transpose() / n;
+    }
+    let min_eigen = cov.symmetric_eigenvalues().min();
+    min_eigen.max(0.0).sqrt() >= MIN_SPREAD
+}
+
+/// Range equations minus the first one form linear system in x, y
+fn closed_form(ranges: &[Range], z: f32) -> Option<Coords> {
+    let first = &ranges[0];
+    let d0 = flat_distance2(first, z);
+    let mut ata = Matrix2::<f32>::zeros();
+    let mut atb = Vector2::<f32>::zeros();
+    for r in ranges[1..].iter() {
+        let a = Vector2::new(
+            2.0 * (r.from[0] - first.from[0]),
+            2.0 * (r.from[1] - first.from[1]),
+        );
+        let b = d0 - flat_distance2(r, z) + r.from[0].powi(2) - first.from[0].powi(2)
+            + r.from[1].powi(2)
+            - first.from[1].powi(2);
+        ata += a * a.transpose();
+        atb += a * b;
+    }
+    let p = ata.try_inverse()? * atb;
+    Some(Coords([p[0], p[1], z]))
+}
+
+fn grid_search(ranges: &[Range], z: f32) -> Coords {
+    let reach = ranges.iter().map(|r| r.distance).fold(0.0, f32::max);
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for r in ranges.iter() {
+        for i in 0..2 {
+            min[i] = min[i].min(r.from[i] - reach);
+            max[i] = max[i].max(r.from[i] + reach);
+        }
+    }
+    let step = ((max[0] - min[0]).max(max[1] - min[1]) / GRID_CELLS as f32).max(MIN_GRID_STEP);
+    let mut best = (f32::MAX, Coords([min[0], min[1], z]));
+    let mut x = min[0];
+    while x <= max[0] {
+        let mut y = min[1];
+        while y <= max[1] {
+            let pos = Coords([x, y, z]);
+            let cost = least_squares::cost(&pos, ranges);
+            if cost < best.0 {
+                best = (cost, pos);
+            }
+            y += step;
+        }
+        x += step;
+    }
+    best.1
+}
+
+/// First position of device at height `z`, `None` until ranges are enough
+/// for unambiguous fix
+pub fn seed(ranges: &[Range], z: f32) -> Option<Coords> {
+    if ranges.len() < least_squares::MIN_RANGES || !spread_enough(ranges) {
+        return None;
+    }
+    let mut candidates = vec![grid_search(ranges, z)];
+    candidates.extend(closed_form(ranges, z));
+    candidates
+        .iter()
+        .map(|c| least_squares::solve(c, ranges))
+        .filter(|c| c.0.iter().all(|v| v.is_finite()))
+        .map(|c| (least_squares::cost(&c, ranges), c))
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+        .map(|(_, c)| c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges_to(anchors: &[[f32; 3]], target: [f32; 3]) -> Vec<Range> {
+        anchors
+            .iter()
+            .map(|a| {
+                let d = ((a[0] - target[0]).powi(2)
+                    + (a[1] - target[1]).powi(2)
+                    + (a[2] - target[2]).powi(2))
+                .sqrt();
+                Range::new(Coords(*a), d)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seed_found_outside_anchors() {
+        let anchors = [[10.0, 10.0, 0.0], [20.0, 10.0, 0.0], [10.0, 20.0, 0.0]];
+        let target = [25.0, 25.0, 0.0];
+        let pos = seed(&ranges_to(&anchors, target), 0.0).unwrap();
+        assert!((pos[0] - target[0]).abs() < 0.01);
+        assert!((pos[1] - target[1]).abs() < 0.01);
+    }
+
+    #[test]
+    fn collinear_anchors_not_enough() {
+        let anchors = [[0.0, 0.0, 0.0], [5.0, 0.0, 0.0], [10.0, 0.0, 0.0]];
+        assert!(seed(&ranges_to(&anchors, [3.0, 4.0, 0.0]), 0.0).is_none());
+        assert!(seed(&ranges_to(&anchors[..2], [3.0, 4.0, 0.0]), 0.0).is_none());
+    }
+}
@@ -0,0 +1,229 @@
+//! Joint solution of several tags using ranges between them.
+//!
+//! Tags ranged to each other are solved together with their anchor ranges,
+//! so tag at the edge of anchor coverage borrows geometry from its peers.
+//! Peers outside of the solved batch act as anchors with uncertain position,
+//! their deviation is added to the range one.
+
+use super::Range;
+use crate::utils::Coords;
+use nalgebra::{DMatrix, DVector, Vector3};
+use serde_derive::{Deserialize, Serialize};
+
+const ITERATIONS: usize = 10;
+const CONVERGENCE: f32 = 1e-4;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// range measurement deviation [m]
+    pub range_sigma: f32,
+    /// deviation of tag from its tracker position [m], keeps weakly
+    /// observed tags in place
+    pub prior_sigma: f32,
+    /// position deviation of peer never solved jointly [m]
+    pub peer_sigma: f32,
+}
+
+/// Other end of tag to tag range
+#[derive(Copy, Clone, Debug)]
+pub enum Peer {
+    /// tag solved in the same batch, index of its node
+    Node(usize),
+    /// tag with fixed estimate and its deviation [m]
+    Known { pos: Coords, sigma: f32 },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PeerRange {
+    pub peer: Peer,
+    pub distance: f32,
+}
+
+/// Tag to solve with all its ranges
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub start: Coords,
+    pub anchors: Vec<Range>,
+    pub peers: Vec<PeerRange>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Estimate {
+    pub coords: Coords,
+    /// horizontal position deviation [m]
+    pub sigma: f32,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            range_sigma: 0.1,
+            prior_sigma: 1.0,
+            peer_sigma: 0.5,
+        }
+    }
+}
+
+fn to_vector(c: &Coords) -> Vector3<f32> {
+    Vector3::new(c[0], c[1], c[2])
+}
+
+/// Gauss-Newton system of ranges between two points, `b` may be fixed
+struct System {
+    h: DMatrix<f32>,
+    g: DVector<f32>,
+}
+
+impl System {
+    fn new(n: usize) -> System {
+        System {
+            h: DMatrix::zeros(3 * n, 3 * n),
+            g: DVector::zeros(3 * n),
+        }
+    }
+
+    fn add_range(
+        &mut self,
+        a: (usize, Vector3<f32>),
+        b: (Option<usize>, Vector3<f32>),
+        d: f32,
+        w: f32,
+    ) {
+        let diff = a.1 - b.1;
+        let dist = diff.norm().max(f32::EPSILON);
+        let j = diff / dist;
+        let r = d - dist;
+        let mut blocks = vec![(a.0, j)];
+        if let Some(k) = b.0 {
+            blocks.push((k, -j));
+        }
+        for &(p, jp) in blocks.iter() {
+            for &(q, jq) in blocks.iter() {
+                let hpq = jp * jq.transpose() * w;
+                for i in 0..3 {
+                    for k in 0..3 {
+                        self.h[(3 * p + i, 3 * q + k)] += hpq[(i, k)];
+                    }
+                }
+            }
+            for i in 0..3 {
+                self.g[3 * p + i] += jp[i] * r * w;
+            }
+        }
+    }
+
+    fn add_prior(&mut self, p: usize, diff: Vector3<f32>, w: [f32; 3]) {
+        for i in 0..3 {
+            self.h[(3 * p + i, 3 * p + i)] += w[i];
+            self.g[3 * p + i] += diff[i] * w[i];
+        }
+    }
+}
+
+/// Solve all nodes together, returns estimates in the same order
+pub fn solve(config: &Config, nodes: &[Node]) -> Vec<Estimate> {
+    let n = nodes.len();
+    let mut p: Vec<Vector3<f32>> = nodes.iter().map(|n| to_vector(&n.start)).collect();
+    let w_range = 1.0 / config.range_sigma.powi(2);
+    // height is poorly observed by ranges, kept much closer to start
+    let w_prior = 1.0 / config.prior_sigma.powi(2);
+    let prior = [w_prior, w_prior, 100.0 * w_prior];
+    let mut covariance = None;
+    for iteration in 0..=ITERATIONS {
+        let mut system = System::new(n);
+        for (i, node) in nodes.iter().enumerate() {
+            for r in node.anchors.iter() {
+                system.add_range((i, p[i]), (None, to_vector(&r.from)), r.distance, w_range);
+            }
+            for r in node.peers.iter() {
+                match r.peer {
+                    // each pair is listed by both nodes, counted once
+                    Peer::Node(k) if k > i => {
+                        system.add_range((i, p[i]), (Some(k), p[k]), r.distance, w_range)
+                    }
+                    Peer::Node(_) => (),
+                    Peer::Known { pos, sigma } => {
+                        let w = 1.0 / (config.range_sigma.powi(2) + sigma.powi(2));
+                        system.add_range((i, p[i]), (None, to_vector(&pos)), r.distance, w);
+                    }
+                }
+            }
+            system.add_prior(i, to_vector(&node.start) - p[i], prior);
+        }
+        let inverse = match system.h.clone().cholesky() {
+            Some(c) => c.inverse(),
+            None => break,
+        };
+        let step = &inverse * &system.g;
+        covariance = Some(inverse);
+        if iteration == ITERATIONS || step.norm() < CONVERGENCE {
+            break;
+        }
+        for (i, pi) in p.iter_mut().enumerate() {
+            *pi += Vector3::new(step[3 * i], step[3 * i + 1], step[3 * i + 2]);
+        }
+    }
+    p.iter()
+        .enumerate()
+        .map(|(i, pi)| {
+            let sigma = match &covariance {
+                Some(c) => (c[(3 * i, 3 * i)] + c[(3 * i + 1, 3 * i + 1)]).sqrt(),
+                None => config.prior_sigma,
+            };
+            Estimate {
+                coords: Coords([pi[0], pi[1], pi[2]]),
+                sigma,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+        (to_vector(&Coords(a)) - to_vector(&Coords(b))).norm()
+    }
+
+    #[test]
+    fn edge_tag_solved_with_peer() {
+        let anchors = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0],
+            [10.0, 10.0, 0.0],
+        ];
+        let inner = [5.0, 5.0, 0.0];
+        let edge = [12.0, 5.0, 0.0];
+        let ranges = |target: [f32; 3], used: &[usize]| -> Vec<Range> {
+            used.iter()
+                .map(|&i| Range::new(Coords(anchors[i]), distance(anchors[i], target)))
+                .collect()
+        };
+        let peer = distance(inner, edge);
+        let nodes = vec![
+            Node {
+                start: Coords([4.0, 6.0, 0.0]),
+                anchors: ranges(inner, &[0, 1, 2, 3]),
+                peers: vec![PeerRange {
+                    peer: Peer::Node(1),
+                    distance: peer,
+                }],
+            },
+            Node {
+                start: Coords([11.0, 4.0, 0.0]),
+                anchors: ranges(edge, &[1, 3]),
+                peers: vec![PeerRange {
+                    peer: Peer::Node(0),
+                    distance: peer,
+                }],
+            },
+        ];
+        let estimates = solve(&Config::default(), &nodes);
+        assert!(distance(estimates[0].coords.0, inner) < 0.01);
+        assert!(distance(estimates[1].coords.0, edge) < 0.05);
+        assert!(estimates[1].sigma > estimates[0].sigma);
+    }
+}
@@ -0,0 +1,75 @@
+//! Damped Gauss-Newton multilateration, started from the previous position.
+
+use super::Range;
+use crate::utils::Coords;
+use nalgebra::{Matrix3, Vector3};
+
+const ITERATIONS: usize = 10;
+/// Damping keeps poorly observed directions (usually z) near previous value
+const DAMPING: f32 = 1e-3;
+const CONVERGENCE: f32 = 1e-4;
+/// Minimal number of ranges giving unambiguous 2D position
+pub const MIN_RANGES: usize = 3;
+
+fn to_vector(c: &Coords) -> Vector3<f32> {
+    Vector3::new(c[0], c[1], c[2])
+}
+
+/// Sum of squared range residuals at given position
+pub fn cost(pos: &Coords, ranges: &[Range]) -> f32 {
+    let p = to_vector(pos);
+    ranges
+        .iter()
+        .map(|r| ((p - to_vector(&r.from)).norm() - r.distance).powi(2))
+        .sum()
+}
+
+pub fn solve(start: &Coords, ranges: &[Range]) -> Coords {
+    if ranges.len() < MIN_RANGES {
+        return *start;
+    }
+    let mut p = to_vector(start);
+    for _ in 0..ITERATIONS {
+        let mut jtj = Matrix3::<f32>::identity() * DAMPING;
+        let mut jtr = Vector3::<f32>::zeros();
+        for r in ranges.iter() {
+            let diff = p - to_vector(&r.from);
+            let dist = diff.norm().max(f32::EPSILON);
+            let j = diff / dist;
+            jtj += j * j.transpose();
+            jtr += j * (r.distance - dist);
+        }
+        let step = match jtj.try_inverse() {
+            Some(inv) => inv * jtr,
+            None => break,
+        };
+        p += step;
+        if step.norm() < CONVERGENCE {
+            break;
+        }
+    }
+    Coords([p[0], p[1], p[2]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converges_to_true_position() {
+        let anchors = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0],
+            [10.0, 10.0, 0.0],
+        ];
+        let target = Vector3::new(3.0, 7.0, 0.0);
+        let ranges: Vec<Range> = anchors
+            .iter()
+            .map(|a| Range::new(Coords(*a), (target - Vector3::from(*a)).norm()))
+            .collect();
+        let pos = solve(&Coords([5.0, 5.0, 0.0]), &ranges);
+        assert!((to_vector(&pos) - target).norm() < 1e-3);
+        assert!(cost(&pos, &ranges) < 1e-4);
+    }
+}
@@ -0,0 +1,136 @@
+//! Position trackers, one of them is selected per zone.
+//!
+//! Tracker gets ranges to devices with known positions and returns new
+//! position of the device together with its updated tracker memory.
+
+pub mod acquisition;
+pub mod cooperative;
+pub mod least_squares;
+pub mod particle;
+
+use crate::inertial::Motion;
+use crate::map::ObstacleMap;
+use crate::utils::{Coords, DevId, Timestamp, Trace};
+use serde_derive::{Deserialize, Serialize};
+
+/// Single distance measured to a device with known position
+#[derive(Copy, Clone, Debug)]
+pub struct Range {
+    pub from: Coords,
+    pub distance: f32,
+}
+
+/// Tracker memory kept per device
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub enum State {
+    #[default]
+    None,
+    Particles(particle::Cloud),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Tracker {
+    #[default]
+    LeastSquares,
+    Particle(particle::Config),
+}
+
+impl Range {
+    pub fn new(from: Coords, distance: f32) -> Range {
+        Range { from, distance }
+    }
+}
+
+impl State {
+    /// Express tracker memory in another frame
+    pub fn transform(&mut self, f: &dyn Fn(&Coords) -> Coords) {
+        match self {
+            State::None => (),
+            State::Particles(cloud) => cloud.transform(f),
+        }
+    }
+}
+
+impl Tracker {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Tracker::LeastSquares => true,
+            Tracker::Particle(config) => config.is_valid(),
+        }
+    }
+
+    /// New position from ranges, started at `prev` moved by `motion` known
+    /// from inertial sensors
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &self,
+        id: DevId,
+        prev: &Trace,
+        state: &State,
+        ranges: &[Range],
+        map: Option<&ObstacleMap>,
+        timestamp: Timestamp,
+        motion: Option<&Motion>,
+    ) -> (Trace, State) {
+        match self {
+            Tracker::LeastSquares => {
+                let coords = match motion {
+                    Some(m) => {
+                        let predicted = m.predict(&prev.coords);
+                        if ranges.len() < least_squares::MIN_RANGES {
+                            predicted
+                        } else {
+                            m.fuse(&predicted, &least_squares::solve(&predicted, ranges))
+                        }
+                    }
+                    None => least_squares::solve(&prev.coords, ranges),
+                };
+                (Trace { coords, timestamp }, State::None)
+            }
+            Tracker::Particle(config) => {
+                let mut cloud = match state {
+                    State::Particles(c) => c.clone(),
+                    State::None => particle::Cloud::new(config, id, prev),
+                };
+                if let Some(m) = motion {
+                    cloud.shift(m.displacement);
+                }
+                let coords = cloud.update(config, ranges, map, timestamp);
+                (Trace { coords, timestamp }, State::Particles(cloud))
+            }
+        }
+    }
+
+    /// New position from position fix with deviation `sigma`, eg. from
+    /// fingerprints, used as a measurement of tracker
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_fix(
+        &self,
+        id: DevId,
+        prev: &Trace,
+        state: &State,
+        fix: &Coords,
+        sigma: f32,
+        map: Option<&ObstacleMap>,
+        timestamp: Timestamp,
+    ) -> (Trace, State) {
+        match self {
+            Tracker::LeastSquares => (
+                Trace {
+                    coords: *fix,
+                    timestamp,
+                },
+                State::None,
+            ),
+            Tracker::Particle(config) => {
+                let mut cloud = match state {
+                    State::Particles(c) => c.clone(),
+                    State::None => particle::Cloud::new(config, id, prev),
+                };
+                let coords = cloud.update_fix(config, fix, sigma, map, timestamp);
+                (Trace { coords, timestamp }, State::Particles(cloud))
+            }
+        }
+    }
+}
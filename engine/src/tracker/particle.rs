@@ -0,0 +1,461 @@
+//! Particle filter tracker, robust to multimodal solutions and obstacles.
+
+use super::Range;
+use crate::map::ObstacleMap;
+use crate::utils::{Coords, DevId, Timestamp, Trace};
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use rand_xorshift::XorShiftRng;
+use serde_derive::{Deserialize, Serialize};
+
+/// Minimal position noise added in prediction step, keeps cloud alive
+const MIN_MOTION_SIGMA: f32 = 0.05;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// number of particles per device
+    pub particles: usize,
+    /// expected device speed deviation [m/s]
+    pub motion_sigma: f32,
+    /// range measurement deviation [m]
+    pub range_sigma: f32,
+    /// fixed RNG seed for reproducible runs, random when not set
+    pub seed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+struct Particle {
+    pos: [f32; 3],
+    #[serde(with = "log_weight_doc")]
+    log_weight: f32,
+}
+
+/// Dead particles weight is -inf, which JSON can't hold, saved as null
+mod log_weight_doc {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(w: &f32, s: S) -> Result<S::Ok, S::Error> {
+        let w = if w.is_finite() { Some(*w) } else { None };
+        serde::Serialize::serialize(&w, s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<f32, D::Error> {
+        let w: Option<f32> = serde::Deserialize::deserialize(d)?;
+        Ok(w.unwrap_or(f32::NEG_INFINITY))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Cloud {
+    particles: Vec<Particle>,
+    rng: XorShiftRng,
+    timestamp: Timestamp,
+    initialized: bool,
+    seed_pos: Coords,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            particles: 500,
+            motion_sigma: 1.0,
+            range_sigma: 0.3,
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    /// Particles and positive finite deviations are needed to track
+    pub fn is_valid(&self) -> bool {
+        let positive = |v: f32| v.is_finite() && v > 0.0;
+        self.particles > 0 && positive(self.motion_sigma) && positive(self.range_sigma)
+    }
+}
+
+fn distance(a: &[f32; 3], b: &Coords) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+impl Cloud {
+    pub fn new(config: &Config, id: DevId, prev: &Trace) -> Cloud {
+        let rng = match config.seed {
+            Some(seed) => XorShiftRng::seed_from_u64(seed ^ id as u64),
+            None => XorShiftRng::from_entropy(),
+        };
+        Cloud {
+            particles: Vec::with_capacity(config.particles),
+            rng,
+            timestamp: prev.timestamp,
+            initialized: false,
+            seed_pos: prev.coords,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Move whole cloud to another frame, eg. during zone handover
+    pub fn transform(&mut self, f: &dyn Fn(&Coords) -> Coords) {
+        for p in self.particles.iter_mut() {
+            p.pos = f(&Coords(p.pos)).0;
+        }
+        self.seed_pos = f(&self.seed_pos);
+    }
+
+    /// Move whole cloud by displacement known from other sensors
+    pub fn shift(&mut self, displacement: [f32; 2]) {
+        for p in self.particles.iter_mut() {
+            p.pos[0] += displacement[0];
+            p.pos[1] += displacement[1];
+        }
+        self.seed_pos[0] += displacement[0];
+        self.seed_pos[1] += displacement[1];
+    }
+
+    /// Spread particles over spheres given by ranges outside of obstacles,
+    /// returns false when none could be placed
+    fn init(&mut self, config: &Config, ranges: &[Range], map: Option<&ObstacleMap>) -> bool {
+        self.particles.clear();
+        if ranges.is_empty() {
+            self.initialized = false;
+            return false;
+        }
+        let n = config.particles;
+        let log_weight = -(n as f32).ln();
+        let mut attempts = 0;
+        while self.particles.len() < n && attempts < 10 * n {
+            let r = &ranges[self.rng.gen_range(0, ranges.len())];
+            let angle: f32 = self.rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+            let pos = [
+                r.from[0] + r.distance * angle.cos(),
+                r.from[1] + r.distance * angle.sin(),
+                self.seed_pos[2],
+            ];
+            attempts += 1;
+            let blocked = match map {
+                Some(m) => m.is_blocked(&Coords(pos)),
+                None => false,
+            };
+            if !blocked {
+                self.particles.push(Particle { pos, log_weight });
+            }
+        }
+        // fewer particles when ranges mostly cross obstacles
+        self.initialized = !self.particles.is_empty();
+        self.initialized
+    }
+
+    /// Spread particles around position fix outside of obstacles, returns
+    /// false when none could be placed
+    fn scatter(
+        &mut self,
+        config: &Config,
+        fix: &Coords,
+        sigma: f32,
+        map: Option<&ObstacleMap>,
+    ) -> bool {
+        self.particles.clear();
+        let n = config.particles;
+        let log_weight = -(n as f32).ln();
+        let xy = match Normal::new(0.0, sigma) {
+            Ok(xy) => xy,
+            Err(_) => {
+                self.initialized = false;
+                return false;
+            }
+        };
+        let mut attempts = 0;
+        while self.particles.len() < n && attempts < 10 * n {
+            let pos = [
+                fix[0] + xy.sample(&mut self.rng),
+                fix[1] + xy.sample(&mut self.rng),
+                fix[2],
+            ];
+            attempts += 1;
+            let blocked = match map {
+                Some(m) => m.is_blocked(&Coords(pos)),
+                None => false,
+            };
+            if !blocked {
+                self.particles.push(Particle { pos, log_weight });
+            }
+        }
+        self.initialized = !self.particles.is_empty();
+        self.initialized
+    }
+
+    fn predict(&mut self, config: &Config, dt: f32, map: Option<&ObstacleMap>) {
+        let sigma = (config.motion_sigma * dt).max(MIN_MOTION_SIGMA);
+        let xy = Normal::new(0.0, sigma).unwrap();
+        let z = Normal::new(0.0, sigma / 10.0).unwrap();
+        let rng = &mut self.rng;
+        for p in self.particles.iter_mut() {
+            let new_pos = [
+                p.pos[0] + xy.sample(rng),
+                p.pos[1] + xy.sample(rng),
+                p.pos[2] + z.sample(rng),
+            ];
+            if let Some(m) = map {
+                let (from, to) = (Coords(p.pos), Coords(new_pos));
+                if m.is_blocked(&to) || m.first_crossing(&from, &to).is_some() {
+                    p.log_weight = f32::NEG_INFINITY;
+                }
+            }
+            p.pos = new_pos;
+        }
+    }
+
+    fn weight(&mut self, config: &Config, ranges: &[Range]) {
+        let k = 0.5 / config.range_sigma.powi(2);
+        for p in self.particles.iter_mut() {
+            for r in ranges.iter() {
+                p.log_weight -= k * (distance(&p.pos, &r.from) - r.distance).powi(2);
+            }
+        }
+    }
+
+    fn weight_fix(&mut self, fix: &Coords, sigma: f32) {
+        let k = 0.5 / sigma.powi(2);
+        for p in self.particles.iter_mut() {
+            p.log_weight -= k * distance(&p.pos, fix).powi(2);
+        }
+    }
+
+    /// Normalize weights, returns false when whole cloud is degenerated
+    fn normalize(&mut self) -> bool {
+        let max = self
+            .particles
+            .iter()
+            .map(|p| p.log_weight)
+            .fold(f32::NEG_INFINITY, f32::max);
+        if !max.is_finite() {
+            return false;
+        }
+        let sum: f32 = self
+            .particles
+            .iter()
+            .map(|p| (p.log_weight - max).exp())
+            .sum();
+        let norm = max + sum.ln();
+        for p in self.particles.iter_mut() {
+            p.log_weight -= norm;
+        }
+        true
+    }
+
+    /// Systematic resampling, performed when effective size drops below half
+    fn resample(&mut self) {
+        let n = self.particles.len();
+        let ess = 1.0
+            / self
+                .particles
+                .iter()
+                .map(|p| (2.0 * p.log_weight).exp())
+                .sum::<f32>();
+        if ess > n as f32 / 2.0 {
+            return;
+        }
+        let step = 1.0 / n as f32;
+        let mut u = self.rng.gen_range(0.0, step);
+        let mut acc = 0.0;
+        let mut resampled = Vec::with_capacity(n);
+        let log_weight = -(n as f32).ln();
+        for p in self.particles.iter() {
+            acc += p.log_weight.exp();
+            while u < acc && resampled.len() < n {
+                resampled.push(Particle {
+                    pos: p.pos,
+                    log_weight,
+                });
+                u += step;
+            }
+        }
+        while resampled.len() < n {
+            let last = *resampled.last().unwrap_or(&self.particles[n - 1]);
+            resampled.push(Particle { log_weight, ..last });
+        }
+        self.particles = resampled;
+    }
+
+    fn mean(&self) -> Coords {
+        let mut pos = [0.0; 3];
+        for p in self.particles.iter() {
+            let w = p.log_weight.exp();
+            for (i, v) in pos.iter_mut().enumerate() {
+                *v += w * p.pos[i];
+            }
+        }
+        Coords(pos)
+    }
+
+    pub fn update(
+        &mut self,
+        config: &Config,
+        ranges: &[Range],
+        map: Option<&ObstacleMap>,
+        timestamp: Timestamp,
+    ) -> Coords {
+        if !self.initialized {
+            if !self.init(config, ranges, map) {
+                return self.seed_pos;
+            }
+        } else {
+            let dt = timestamp.saturating_sub(self.timestamp) as f32 / 1000.0;
+            self.predict(config, dt, map);
+        }
+        self.timestamp = timestamp;
+        self.weight(config, ranges);
+        if !self.normalize() {
+            // every particle is inconsistent with measures, start over
+            self.seed_pos = self.mean();
+            if !self.init(config, ranges, map) {
+                return self.seed_pos;
+            }
+            self.weight(config, ranges);
+            if !self.normalize() {
+                self.initialized = false;
+                return self.seed_pos;
+            }
+        }
+        let pos = self.mean();
+        self.resample();
+        self.seed_pos = pos;
+        pos
+    }
+
+    /// Update with position fix of given deviation instead of ranges, eg.
+    /// from fingerprints
+    pub fn update_fix(
+        &mut self,
+        config: &Config,
+        fix: &Coords,
+        sigma: f32,
+        map: Option<&ObstacleMap>,
+        timestamp: Timestamp,
+    ) -> Coords {
+        if !self.initialized {
+            if !self.scatter(config, fix, sigma, map) {
+                return *fix;
+            }
+        } else {
+            let dt = timestamp.saturating_sub(self.timestamp) as f32 / 1000.0;
+            self.predict(config, dt, map);
+        }
+        self.timestamp = timestamp;
+        self.weight_fix(fix, sigma);
+        if !self.normalize() {
+            if !self.scatter(config, fix, sigma, map) {
+                return *fix;
+            }
+            self.weight_fix(fix, sigma);
+            self.normalize();
+        }
+        let pos = self.mean();
+        self.resample();
+        self.seed_pos = pos;
+        pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Segment;
+
+    fn ranges_to(target: [f32; 3]) -> Vec<Range> {
+        let anchors = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0],
+            [10.0, 10.0, 0.0],
+        ];
+        anchors
+            .iter()
+            .map(|a| Range::new(Coords(*a), distance(&target, &Coords(*a))))
+            .collect()
+    }
+
+    fn run(config: &Config, map: Option<&ObstacleMap>) -> Coords {
+        let start = Trace {
+            coords: Coords([0.0, 0.0, 0.0]),
+            timestamp: 0,
+        };
+        let mut cloud = Cloud::new(config, 7, &start);
+        let ranges = ranges_to([3.0, 4.0, 0.0]);
+        let mut pos = start.coords;
+        for ts in 1..20 {
+            pos = cloud.update(config, &ranges, map, ts * 100);
+        }
+        pos
+    }
+
+    #[test]
+    fn fixed_seed_is_reproducible() {
+        let config = Config {
+            seed: Some(42),
+            ..Config::default()
+        };
+        let a = run(&config, None);
+        let b = run(&config, None);
+        assert_eq!(a.0, b.0);
+        assert!(distance(&[3.0, 4.0, 0.0], &a) < 0.3);
+    }
+
+    #[test]
+    fn particles_respect_walls() {
+        let config = Config {
+            seed: Some(1),
+            ..Config::default()
+        };
+        let mut map = ObstacleMap::new();
+        map.walls.push(Segment::new([5.0, -20.0], [5.0, 20.0]));
+        let start = Trace {
+            coords: Coords([7.0, 4.0, 0.0]),
+            timestamp: 0,
+        };
+        let mut cloud = Cloud::new(&config, 7, &start);
+        for ts in 1..10 {
+            cloud.update(&config, &ranges_to([7.0, 4.0, 0.0]), Some(&map), ts * 100);
+        }
+        // target moves behind the wall, the cloud can't follow it
+        for ts in 10..30 {
+            let pos = cloud.update(&config, &ranges_to([3.0, 4.0, 0.0]), Some(&map), ts * 100);
+            assert!(pos[0] > 5.0);
+            assert!(cloud
+                .particles
+                .iter()
+                .filter(|p| p.log_weight.is_finite())
+                .all(|p| p.pos[0] > 5.0));
+        }
+    }
+
+    #[test]
+    fn invalid_config_rejected() {
+        assert!(Config::default().is_valid());
+        for config in [
+            Config {
+                particles: 0,
+                ..Config::default()
+            },
+            Config {
+                range_sigma: 0.0,
+                ..Config::default()
+            },
+            Config {
+                motion_sigma: -1.0,
+                ..Config::default()
+            },
+        ]
+        .iter()
+        {
+            assert!(!config.is_valid());
+        }
+    }
+}
@@ -4,9 +4,34 @@ use std::ops;
 
 pub type DevId = u32;
 
-pub type Timestamp = u32;
+pub type ZoneId = u32;
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+/// Milliseconds, wide enough to never wrap
+pub type Timestamp = u64;
+
+/// Extend wrapping 32-bit timestamp, eg. from legacy device, to the full
+/// value nearest to `reference`. Reference 0 means nothing was received
+/// yet, raw value is taken as is then.
+pub fn unwrap_timestamp(raw: u32, reference: Timestamp) -> Timestamp {
+    if reference == 0 {
+        return raw as Timestamp;
+    }
+    // signed distance from reference, modulo 2^32
+    let diff = raw.wrapping_sub(reference as u32) as i32 as i64;
+    (reference as i64 + diff).max(0) as Timestamp
+}
+
+/// Timestamp received from device. Values fitting in 32 bits may come from
+/// legacy senders and are unwrapped against `reference`, wider are exact.
+pub fn parse_timestamp(raw: u64, reference: Timestamp) -> Timestamp {
+    if raw <= u32::MAX as u64 {
+        unwrap_timestamp(raw as u32, reference)
+    } else {
+        raw
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub struct Coords(pub [f32; 3]);
 
 impl ops::Index<usize> for Coords {
@@ -22,32 +47,82 @@ impl ops::IndexMut<usize> for Coords {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub struct Trace {
     pub coords: Coords,
     pub timestamp: Timestamp,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Scent(VecDeque<Trace>);
+/// Most recent traces first, holds at most `depth` of them
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Scent {
+    traces: VecDeque<Trace>,
+    depth: usize,
+}
 
 impl Scent {
     pub fn new() -> Scent {
-        Scent(VecDeque::with_capacity(10))
+        Scent::with_capacity(10)
     }
     pub fn with_capacity(cap: usize) -> Scent {
-        Scent(VecDeque::with_capacity(cap))
+        Scent {
+            traces: VecDeque::with_capacity(cap),
+            depth: cap,
+        }
     }
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.traces.len()
     }
     pub fn add(&mut self, trace: Trace) {
-        if self.0.len() > self.0.capacity() {
-            self.0.pop_back();
-        }
-        self.0.push_front(trace);
+        self.traces.truncate(self.depth.saturating_sub(1));
+        self.traces.push_front(trace);
     }
     pub fn get(&self, how_old: usize) -> Option<&Trace> {
-        self.0.get(how_old)
+        self.traces.get(how_old)
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Trace> {
+        self.traces.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_timestamp_unwrapped() {
+        let wrap: Timestamp = 1 << 32;
+        assert_eq!(unwrap_timestamp(100, 90), 100);
+        assert_eq!(unwrap_timestamp(80, 90), 80);
+        // legacy sender wrapped just after reference
+        assert_eq!(unwrap_timestamp(5, wrap - 10), wrap + 5);
+        // late measure from before the wrap
+        assert_eq!(unwrap_timestamp(u32::MAX - 5, wrap + 10), wrap - 6);
+        assert_eq!(unwrap_timestamp(7, 3 * wrap + 1), 3 * wrap + 7);
+        assert_eq!(parse_timestamp(wrap + 1, 0), wrap + 1);
+    }
+
+    #[test]
+    fn scent_keeps_depth() {
+        let mut scent = Scent::with_capacity(3);
+        for timestamp in 0..10 {
+            let coords = Coords([0.0; 3]);
+            scent.add(Trace { coords, timestamp });
+        }
+        assert_eq!(scent.len(), 3);
+        assert_eq!(scent.get(0).unwrap().timestamp, 9);
+        assert_eq!(scent.get(2).unwrap().timestamp, 7);
+    }
+
+    #[test]
+    fn first_legacy_timestamp_taken_as_is() {
+        let wrap: Timestamp = 1 << 32;
+        let first = parse_timestamp(u32::MAX as u64 - 5, 0);
+        assert_eq!(first, wrap - 6);
+        assert_eq!(unwrap_timestamp(1 << 31, 0), 1 << 31);
+        // clock keeps running across the wrap
+        let next = parse_timestamp(u32::MAX as u64, first);
+        assert_eq!(next, wrap - 1);
+        assert_eq!(parse_timestamp(3, next), wrap + 3);
     }
 }
@@ -3,93 +3,1422 @@
 */
 
 use log::{info, trace};
+use rayon::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
 
+use crate::baro;
+use crate::clock::{Clock, SystemClock};
 use crate::device;
+use crate::event::{Event, Geofence, Subscriber};
+use crate::fingerprint;
+use crate::gating;
+use crate::geo;
+use crate::health;
+use crate::history::History;
+use crate::inertial;
+use crate::map;
 use crate::measure;
-use crate::utils::{DevId, Timestamp, Trace};
+use crate::site;
+use crate::snapshot;
+use crate::stationary;
+use crate::tracker;
+use crate::utils::{Coords, DevId, Timestamp, Trace, ZoneId};
+
+type LinkKey = (DevId, DevId);
 
 pub struct Zone {
-    pub id: u32,
-    measures: Vec<measure::List>,
+    pub id: ZoneId,
+    measures: HashMap<LinkKey, measure::List>,
+    // devices connected by measures, ordered for deterministic solving
+    adjacency: HashMap<DevId, BTreeSet<DevId>>,
+    // devices kept in insertion order, `index` maps id to position
     devices: Vec<device::Data>,
+    index: HashMap<DevId, usize>,
+    geo_ref: Option<geo::Reference>,
+    map: Option<map::ObstacleMap>,
+    tracker: tracker::Tracker,
+    liveness: device::LivenessTimeouts,
+    liveness_events: Vec<LivenessEvent>,
+    epoch_window: Option<Timestamp>,
+    epoch: Vec<(measure::Distance, bool)>,
+    parallel: bool,
+    placement: site::Placement,
+    // area covered by zone in local frame, unbounded when not set
+    boundary: Option<map::Polygon>,
+    // newest measure timestamp, reference for legacy 32-bit timestamps
+    latest: Timestamp,
+    clock: Arc<dyn Clock>,
+    subscribers: Vec<Box<dyn Subscriber>>,
+    geofences: Vec<Geofence>,
+    // names of geofences every tag is inside of
+    fence_state: HashMap<DevId, BTreeSet<String>>,
+    history: Option<History>,
+    gating: Option<gating::Config>,
+    stationary: Option<stationary::Config>,
+    cooperative: Option<tracker::cooperative::Config>,
+    health: Option<health::Monitor>,
+    inertial: inertial::Config,
+    barometer: Option<baro::Config>,
+    /// reference anchor pressure [Pa] with its time
+    reference_pressure: Option<(f32, Timestamp)>,
+    fingerprint: fingerprint::Config,
+    /// locates tags where anchors are too few, `None` disables it
+    fingerprints: Option<fingerprint::Database>,
+    /// newest signal strength of anchors seen by every tag
+    rssi: HashMap<DevId, BTreeMap<DevId, (f32, Timestamp)>>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ExitCode {
+    Ok,
+    UnknownDevice,
+    AlreadyExist,
+    /// measure between devices not known in the zone
+    UnknownLink,
+    /// operation not allowed for device role, eg. moving a tag like an anchor
+    InvalidRole,
+    /// non finite position or distance, measure of device to itself
+    InvalidArgument,
+    /// zone not handled by zone manager
+    UnknownZone,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LivenessEvent {
+    Lost(DevId),
+    Recovered(DevId),
+}
+
+/// Name of barometer offset in `Event::Calibrated`
+pub const BARO_OFFSET: &str = "baro_offset";
+
+fn link_key(id1: DevId, id2: DevId) -> LinkKey {
+    (min(id1, id2), max(id1, id2))
+}
+
+fn is_finite(pos: &Coords) -> bool {
+    pos.0.iter().all(|v| v.is_finite())
+}
+
+/// Read only view of zone state needed to solve device position
+struct SolveContext<'a> {
+    devices: &'a [device::Data],
+    index: &'a HashMap<DevId, usize>,
+    measures: &'a HashMap<LinkKey, measure::List>,
+    adjacency: &'a HashMap<DevId, BTreeSet<DevId>>,
+    tracker: &'a tracker::Tracker,
+    map: Option<&'a map::ObstacleMap>,
+    gating: Option<&'a gating::Config>,
+    cooperative: Option<&'a tracker::cooperative::Config>,
+    inertial: &'a inertial::Config,
+    fingerprint: &'a fingerprint::Config,
+    fingerprints: Option<&'a fingerprint::Database>,
+}
+
+/// New position of device with tracker memory, how gating judged it
+struct Solution {
+    pos: Trace,
+    /// position before gating
+    raw: Trace,
+    state: tracker::State,
+    /// set when ranges were sufficient for a real fix
+    fixed: bool,
+    outcome: gating::Outcome,
+    /// distances to anchors used for the fix
+    ranges: Vec<(DevId, f32)>,
+    /// position deviation found by joint solution
+    sigma: Option<f32>,
+    /// located from fingerprints, ranges are too few to judge anchors
+    fingerprint: bool,
+}
+
+impl<'a> SolveContext<'a> {
+    fn device(&self, id: DevId) -> Option<&'a device::Data> {
+        self.index.get(&id).map(|&idx| &self.devices[idx])
+    }
+
+    /// Ranges of device to anchors, with anchor ids
+    fn ranges(&self, id: DevId, timestamp: Timestamp) -> Vec<(DevId, tracker::Range)> {
+        self.adjacency
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|&other| {
+                let anchor = self
+                    .device(other)
+                    .filter(|x| x.role() == device::Role::Anchor)?;
+                let m = self.measures.get(&link_key(id, other))?;
+                let from = anchor.estimate_position(timestamp).coords;
+                Some((other, tracker::Range::new(from, m.estimate(timestamp))))
+            })
+            .collect()
+    }
+
+    fn solve(&self, idx: usize, timestamp: Timestamp) -> Solution {
+        let dev = &self.devices[idx];
+        let anchor_ranges = self.ranges(dev.id(), timestamp);
+        let ranges: Vec<tracker::Range> = anchor_ranges.iter().map(|(_, r)| *r).collect();
+        let mut prev = dev.estimate_position(timestamp);
+        let mut track = dev.track_state();
+        let mut motion = dev.inertial_state().pending(self.inertial);
+        if let Some(fix) = self.match_ranges(&anchor_ranges) {
+            // fingerprint fix is measurement of tracker like ranges are
+            if !dev.has_fix() {
+                track = &tracker::State::None;
+            }
+            let sigma = self.fingerprint.sigma;
+            let (mut pos, state) =
+                self.tracker
+                    .update_fix(dev.id(), &prev, track, &fix, sigma, self.map, timestamp);
+            if let (Some(map), true) = (self.map, dev.has_fix()) {
+                pos.coords = map.constrain(&prev.coords, &pos.coords);
+            }
+            return Solution {
+                pos,
+                raw: pos,
+                state,
+                fixed: true,
+                outcome: gating::Outcome::Accepted,
+                ranges: anchor_ranges
+                    .into_iter()
+                    .map(|(id, r)| (id, r.distance))
+                    .collect(),
+                sigma: Some(sigma),
+                fingerprint: true,
+            };
+        }
+        if !dev.has_fix() {
+            // acquisition, tracking starts from globally found position
+            match tracker::acquisition::seed(&ranges, prev.coords[2]) {
+                Some(coords) => {
+                    prev.coords = coords;
+                    track = &tracker::State::None;
+                    motion = None;
+                }
+                None => {
+                    return Solution {
+                        pos: prev,
+                        raw: prev,
+                        state: tracker::State::None,
+                        fixed: false,
+                        outcome: gating::Outcome::Accepted,
+                        ranges: Vec::new(),
+                        sigma: None,
+                        fingerprint: false,
+                    }
+                }
+            }
+        }
+        let (mut pos, state) = self.tracker.update(
+            dev.id(),
+            &prev,
+            track,
+            &ranges,
+            self.map,
+            timestamp,
+            motion.as_ref(),
+        );
+        if let Some(map) = self.map {
+            pos.coords = map.constrain(&prev.coords, &pos.coords);
+        }
+        Solution {
+            pos,
+            raw: pos,
+            state,
+            fixed: ranges.len() >= tracker::least_squares::MIN_RANGES,
+            outcome: gating::Outcome::Accepted,
+            ranges: anchor_ranges
+                .into_iter()
+                .map(|(id, r)| (id, r.distance))
+                .collect(),
+            sigma: None,
+            fingerprint: false,
+        }
+    }
+
+    /// Position from range fingerprints when anchors are too few to solve
+    fn match_ranges(&self, ranges: &[(DevId, tracker::Range)]) -> Option<Coords> {
+        let db = self
+            .fingerprints
+            .filter(|db| db.signal == fingerprint::Signal::Range)?;
+        if ranges.is_empty() || ranges.len() >= tracker::least_squares::MIN_RANGES {
+            return None;
+        }
+        let values = ranges.iter().map(|(id, r)| (*id, r.distance)).collect();
+        db.locate(self.fingerprint, &values)
+    }
+
+    /// Check solved position against motion limits of device class
+    fn gate(&self, idx: usize, solution: &mut Solution) {
+        let dev = &self.devices[idx];
+        let config = match self.gating {
+            Some(c) if solution.fixed && dev.has_fix() => c,
+            _ => return,
+        };
+        let limits = config.limits(dev.metadata().class.as_deref());
+        if let (Some(limits), Some(last)) = (limits, dev.recent_position(0)) {
+            let prev = dev.recent_position(1);
+            solution.raw = solution.pos;
+            let (pos, outcome) = config.check(limits, dev.gate_state(), last, prev, &solution.pos);
+            solution.pos = pos;
+            solution.outcome = outcome;
+        }
+    }
+
+    /// Ranges of tag to other tags
+    fn peer_ranges(&self, id: DevId, timestamp: Timestamp) -> Vec<(&'a device::Data, f32)> {
+        self.adjacency
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|&other| {
+                let peer = self
+                    .device(other)
+                    .filter(|x| x.role() == device::Role::Tag)?;
+                let m = self.measures.get(&link_key(id, other))?;
+                Some((peer, m.estimate(timestamp)))
+            })
+            .collect()
+    }
+
+    /// Refine solved tags jointly with ranges between them. Tags still
+    /// acquiring their first position are left out.
+    fn cooperate(&self, jobs: &[(usize, Timestamp)], solutions: &mut [Solution]) {
+        let config = match self.cooperative {
+            Some(c) => c,
+            None => return,
+        };
+        let solved: Vec<usize> = (0..jobs.len())
+            .filter(|&j| solutions[j].fixed || self.devices[jobs[j].0].has_fix())
+            .collect();
+        let node_of: HashMap<DevId, usize> = solved
+            .iter()
+            .enumerate()
+            .map(|(n, &j)| (self.devices[jobs[j].0].id(), n))
+            .collect();
+        let nodes: Vec<tracker::cooperative::Node> = solved
+            .iter()
+            .map(|&j| {
+                let (idx, ts) = jobs[j];
+                let id = self.devices[idx].id();
+                let peers = self
+                    .peer_ranges(id, ts)
+                    .into_iter()
+                    .filter_map(|(peer, distance)| {
+                        let peer = match node_of.get(&peer.id()) {
+                            Some(&n) => tracker::cooperative::Peer::Node(n),
+                            None if peer.has_fix() => tracker::cooperative::Peer::Known {
+                                pos: peer.estimate_position(ts).coords,
+                                sigma: peer.position_sigma().unwrap_or(config.peer_sigma),
+                            },
+                            None => return None,
+                        };
+                        Some(tracker::cooperative::PeerRange { peer, distance })
+                    })
+                    .collect();
+                tracker::cooperative::Node {
+                    start: solutions[j].pos.coords,
+                    anchors: self.ranges(id, ts).into_iter().map(|(_, r)| r).collect(),
+                    peers,
+                }
+            })
+            .collect();
+        if nodes.iter().all(|n| n.peers.is_empty()) {
+            return;
+        }
+        let estimates = tracker::cooperative::solve(config, &nodes);
+        for ((&j, node), estimate) in solved.iter().zip(nodes.iter()).zip(estimates) {
+            let solution = &mut solutions[j];
+            solution.pos.coords = estimate.coords;
+            solution.raw = solution.pos;
+            solution.sigma = Some(estimate.sigma);
+            solution.fixed =
+                node.anchors.len() + node.peers.len() >= tracker::least_squares::MIN_RANGES;
+        }
+    }
 }
 
-#[derive(PartialEq, Debug)]
-pub enum ExitCode {
-    Ok,
-    UnknownDevice,
-    AlreadyExist,
-}
+impl Zone {
+    pub fn new(id: ZoneId) -> Zone {
+        let zone = Zone {
+            id: id,
+            measures: HashMap::new(),
+            adjacency: HashMap::new(),
+            devices: Vec::new(),
+            index: HashMap::new(),
+            geo_ref: None,
+            map: None,
+            tracker: tracker::Tracker::default(),
+            liveness: device::LivenessTimeouts::default(),
+            liveness_events: Vec::new(),
+            epoch_window: None,
+            epoch: Vec::new(),
+            parallel: false,
+            placement: site::Placement::default(),
+            boundary: None,
+            latest: 0,
+            clock: Arc::new(SystemClock),
+            subscribers: Vec::new(),
+            geofences: Vec::new(),
+            fence_state: HashMap::new(),
+            history: None,
+            gating: None,
+            stationary: None,
+            cooperative: None,
+            health: None,
+            inertial: inertial::Config::default(),
+            barometer: None,
+            reference_pressure: None,
+            fingerprint: fingerprint::Config::default(),
+            fingerprints: None,
+            rssi: HashMap::new(),
+        };
+        zone
+    }
+
+    /// Anchor zone local frame to the globe, `None` removes the reference
+    pub fn set_geo_reference(&mut self, reference: Option<geo::Reference>) {
+        self.geo_ref = reference;
+    }
+
+    pub fn geo_reference(&self) -> Option<&geo::Reference> {
+        self.geo_ref.as_ref()
+    }
+
+    /// Enable map matching constraints, `None` disables them
+    pub fn set_map(&mut self, map: Option<map::ObstacleMap>) {
+        self.map = map;
+    }
+
+    pub fn map(&self) -> Option<&map::ObstacleMap> {
+        self.map.as_ref()
+    }
+
+    /// Place zone local frame in site frame shared with other zones
+    pub fn set_placement(&mut self, placement: site::Placement) {
+        self.placement = placement;
+    }
+
+    pub fn placement(&self) -> &site::Placement {
+        &self.placement
+    }
+
+    /// Area covered by zone in local frame, used for handover between zones
+    pub fn set_boundary(&mut self, boundary: Option<map::Polygon>) {
+        self.boundary = boundary;
+    }
+
+    pub fn boundary(&self) -> Option<&map::Polygon> {
+        self.boundary.as_ref()
+    }
+
+    /// Zone boundary expressed in site frame
+    pub fn site_boundary(&self) -> Option<map::Polygon> {
+        let boundary = self.boundary.as_ref()?;
+        let points = boundary
+            .points
+            .iter()
+            .map(|p| {
+                let site = self.placement.to_site(&Coords([p[0], p[1], 0.0]));
+                [site[0], site[1]]
+            })
+            .collect();
+        Some(map::Polygon::new(points))
+    }
+
+    /// Check if local position lies inside zone, always true when unbounded
+    pub fn covers(&self, pos: &Coords) -> bool {
+        match &self.boundary {
+            Some(b) => b.contains([pos[0], pos[1]]),
+            None => true,
+        }
+    }
+
+    /// Select tracker used for tags, drops tracker state of every device
+    pub fn set_tracker(&mut self, tracker: tracker::Tracker) -> ExitCode {
+        if !tracker.is_valid() {
+            return ExitCode::InvalidArgument;
+        }
+        for dev in self.devices.iter_mut() {
+            dev.set_track_state(tracker::State::None);
+        }
+        self.tracker = tracker;
+        ExitCode::Ok
+    }
+
+    pub fn tracker(&self) -> &tracker::Tracker {
+        &self.tracker
+    }
+
+    /// Freeze positions of devices standing still, `None` disables it
+    pub fn set_stationary(&mut self, stationary: Option<stationary::Config>) {
+        for dev in self.devices.iter_mut() {
+            *dev.motion_state_mut() = stationary::State::default();
+        }
+        self.stationary = stationary;
+    }
+
+    pub fn stationary(&self) -> Option<&stationary::Config> {
+        self.stationary.as_ref()
+    }
+
+    /// Solve tags ranged to each other jointly, `None` solves every tag
+    /// against anchors only
+    pub fn set_cooperative(&mut self, cooperative: Option<tracker::cooperative::Config>) {
+        self.cooperative = cooperative;
+    }
+
+    pub fn cooperative(&self) -> Option<&tracker::cooperative::Config> {
+        self.cooperative.as_ref()
+    }
+
+    /// Watch anchor residuals to find moved or failing ones, `None`
+    /// disables it
+    pub fn set_anchor_health(&mut self, config: Option<health::Config>) {
+        self.health = config.map(health::Monitor::new);
+        if let Some(monitor) = self.health.as_mut() {
+            for dev in self.devices.iter() {
+                if dev.role() == device::Role::Anchor {
+                    monitor.watch(dev.id(), max(dev.last_activity(), self.latest));
+                }
+            }
+        }
+    }
+
+    pub fn anchor_health(&self) -> Option<&health::Monitor> {
+        self.health.as_ref()
+    }
+
+    fn update_anchor_health(&mut self, fix: &Trace, ranges: &[(DevId, f32)], residuals: bool) {
+        let monitor = match self.health.as_mut() {
+            Some(m) => m,
+            None => return,
+        };
+        let mut events = Vec::new();
+        for &(id, measured) in ranges.iter() {
+            let anchor = match self.index.get(&id) {
+                Some(&idx) => self.devices[idx].estimate_position(fix.timestamp).coords,
+                None => continue,
+            };
+            let findings = if residuals {
+                monitor.record(id, &anchor, &fix.coords, measured, fix.timestamp)
+            } else {
+                monitor.seen(id, fix.timestamp)
+            };
+            for finding in findings {
+                let zone = self.id;
+                events.push(match finding {
+                    health::Finding::Status(status) => {
+                        info!("Anchor {} is {:?}", id, status);
+                        Event::AnchorStatus { zone, id, status }
+                    }
+                    health::Finding::Moved(offset) => {
+                        info!("Anchor {} moved by {:?}", id, offset.0);
+                        Event::AnchorMoved { zone, id, offset }
+                    }
+                });
+            }
+        }
+        for event in events {
+            self.emit(event);
+        }
+    }
+
+    /// Limit speed and acceleration of tags per class, `None` disables it
+    pub fn set_gating(&mut self, gating: Option<gating::Config>) {
+        for dev in self.devices.iter_mut() {
+            dev.gate_state_mut().accept();
+        }
+        self.gating = gating;
+    }
+
+    pub fn gating(&self) -> Option<&gating::Config> {
+        self.gating.as_ref()
+    }
+
+    pub fn set_liveness_timeouts(&mut self, timeouts: device::LivenessTimeouts) {
+        self.liveness = timeouts;
+    }
+
+    pub fn liveness_timeouts(&self) -> &device::LivenessTimeouts {
+        &self.liveness
+    }
+
+    /// Report devices lost since last check and devices recovered by new
+    /// measurements
+    pub fn check_liveness(&mut self, now: Timestamp) -> Vec<LivenessEvent> {
+        let ret = self.flush_expired_epoch(now);
+        if ret != ExitCode::Ok {
+            info!("Epoch flush failed, {:?}", ret);
+        }
+        for dev in self.devices.iter_mut() {
+            if dev.liveness(now, &self.liveness) == device::Liveness::Lost && dev.mark_lost() {
+                info!("Device {} lost", dev.id());
+                self.liveness_events.push(LivenessEvent::Lost(dev.id()));
+            }
+        }
+        let missing = match self.health.as_mut() {
+            Some(m) => m.check_missing(now),
+            None => Vec::new(),
+        };
+        for id in missing {
+            info!("Anchor {} missing", id);
+            let status = health::Status::Missing;
+            self.emit(Event::AnchorStatus {
+                zone: self.id,
+                id,
+                status,
+            });
+        }
+        let events = std::mem::take(&mut self.liveness_events);
+        for e in events.iter() {
+            let event = match *e {
+                LivenessEvent::Lost(id) => Event::DeviceLost { zone: self.id, id },
+                LivenessEvent::Recovered(id) => Event::DeviceRecovered { zone: self.id, id },
+            };
+            self.emit(event);
+        }
+        events
+    }
+
+    /// Time source used when no timestamp is given, system clock by default
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    /// Receive events about everything zone learns
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    fn emit(&mut self, event: Event) {
+        for s in self.subscribers.iter_mut() {
+            s.notify(&event);
+        }
+    }
+
+    /// Keep trajectories of tags for given time [ms], `None` disables it
+    pub fn set_history_duration(&mut self, duration: Option<Timestamp>) {
+        self.history = duration.map(History::new);
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Areas which tags entering and leaving are reported
+    pub fn set_geofences(&mut self, geofences: Vec<Geofence>) {
+        self.geofences = geofences;
+        self.fence_state.clear();
+    }
+
+    pub fn geofences(&self) -> &[Geofence] {
+        &self.geofences
+    }
+
+    fn update_geofences(&mut self, id: DevId, pos: &Coords) {
+        if self.geofences.is_empty() {
+            return;
+        }
+        let inside: BTreeSet<String> = self
+            .geofences
+            .iter()
+            .filter(|f| f.area.contains([pos[0], pos[1]]))
+            .map(|f| f.name.clone())
+            .collect();
+        let prev = self
+            .fence_state
+            .insert(id, inside.clone())
+            .unwrap_or_default();
+        for fence in prev.difference(&inside) {
+            let fence = fence.clone();
+            self.emit(Event::GeofenceLeft {
+                zone: self.id,
+                id,
+                fence,
+            });
+        }
+        for fence in inside.difference(&prev) {
+            let fence = fence.clone();
+            self.emit(Event::GeofenceEntered {
+                zone: self.id,
+                id,
+                fence,
+            });
+        }
+    }
+
+    /// Liveness check at current clock time
+    pub fn check_liveness_now(&mut self) -> Vec<LivenessEvent> {
+        let now = self.now();
+        self.check_liveness(now)
+    }
+
+    fn touch_device(&mut self, id: DevId, timestamp: Timestamp) {
+        if let Some(dev) = self.device_mut(id) {
+            if dev.touch(timestamp) {
+                info!("Device {} recovered", id);
+                self.liveness_events.push(LivenessEvent::Recovered(id));
+            }
+        }
+    }
+
+    pub fn to_wgs84(&self, coords: &Coords) -> Option<geo::Wgs84> {
+        self.geo_ref.map(|r| r.to_wgs84(coords))
+    }
+
+    pub fn from_wgs84(&self, pos: &geo::Wgs84) -> Option<Coords> {
+        self.geo_ref.map(|r| r.to_local(pos))
+    }
+
+    fn device_mut(&mut self, id: DevId) -> Option<&mut device::Data> {
+        match self.index.get(&id) {
+            Some(&idx) => Some(&mut self.devices[idx]),
+            None => None,
+        }
+    }
+
+    fn insert_device(&mut self, dev: device::Data) -> ExitCode {
+        if self.index.contains_key(&dev.id()) {
+            return ExitCode::AlreadyExist;
+        }
+        let event = Event::DeviceCreated {
+            zone: self.id,
+            id: dev.id(),
+            role: dev.role(),
+        };
+        if let Some(monitor) = self.health.as_mut() {
+            if dev.role() == device::Role::Anchor {
+                monitor.watch(dev.id(), dev.last_activity());
+            }
+        }
+        self.index.insert(dev.id(), self.devices.len());
+        self.devices.push(dev);
+        self.emit(event);
+        ExitCode::Ok
+    }
+
+    /// Insert device registered now, its activity starts at newest measure
+    fn register_device(&mut self, mut dev: device::Data) -> ExitCode {
+        dev.touch(self.latest);
+        self.insert_device(dev)
+    }
+
+    /// Add device with unknown position, first fix is searched globally
+    /// once its ranges are enough
+    pub fn add_new_device(&mut self, id: DevId) -> ExitCode {
+        self.register_device(device::Data::new(id))
+    }
+
+    pub fn add_device(&mut self, id: DevId, pos: [i32; 3]) -> ExitCode {
+        self.register_device(device::Data::new_with_pos(id, pos))
+    }
+
+    /// Add device with fixed position, used as a reference for tags
+    pub fn add_anchor(&mut self, id: DevId, pos: Coords) -> ExitCode {
+        if !is_finite(&pos) {
+            return ExitCode::InvalidArgument;
+        }
+        self.register_device(device::Data::new_with_role(id, pos, device::Role::Anchor))
+    }
+
+    /// Remove device together with all measures it takes part in
+    pub fn remove_device(&mut self, id: DevId) -> ExitCode {
+        match self.take_device(id) {
+            Some(_) => {
+                info!("Device {} removed", id);
+                ExitCode::Ok
+            }
+            None => ExitCode::UnknownDevice,
+        }
+    }
+
+    /// Remove device and its measures, returning its state, eg. for handover
+    pub fn take_device(&mut self, id: DevId) -> Option<device::Data> {
+        let idx = self.index.remove(&id)?;
+        let dev = self.devices.remove(idx);
+        for dev in self.devices[idx..].iter() {
+            *self.index.get_mut(&dev.id()).unwrap() -= 1;
+        }
+        self.remove_device_links(id);
+        self.fence_state.remove(&id);
+        if let Some(history) = self.history.as_mut() {
+            history.remove(id);
+        }
+        if let Some(monitor) = self.health.as_mut() {
+            monitor.forget(id);
+        }
+        Some(dev)
+    }
+
+    /// Add device taken from another zone, its state is kept
+    pub fn put_device(&mut self, dev: device::Data) -> ExitCode {
+        self.insert_device(dev)
+    }
+
+    /// Forget measures of device, eg. after anchor has been moved
+    pub fn remove_device_links(&mut self, id: DevId) -> usize {
+        let neighbours = self.adjacency.remove(&id).unwrap_or_default();
+        for other in neighbours.iter() {
+            self.measures.remove(&link_key(id, *other));
+            if let Some(adj) = self.adjacency.get_mut(other) {
+                adj.remove(&id);
+            }
+        }
+        neighbours.len()
+    }
+
+    pub fn remove_link(&mut self, id1: DevId, id2: DevId) -> ExitCode {
+        if self.measures.remove(&link_key(id1, id2)).is_none() {
+            return ExitCode::UnknownLink;
+        }
+        for (a, b) in [(id1, id2), (id2, id1)].iter() {
+            if let Some(adj) = self.adjacency.get_mut(a) {
+                adj.remove(b);
+            }
+        }
+        ExitCode::Ok
+    }
+
+    /// Move anchor to new place, its measures history is dropped
+    pub fn move_anchor(&mut self, id: DevId, pos: Coords, timestamp: Timestamp) -> ExitCode {
+        if !is_finite(&pos) {
+            return ExitCode::InvalidArgument;
+        }
+        let dev = match self.device_mut(id) {
+            Some(d) => d,
+            None => return ExitCode::UnknownDevice,
+        };
+        if dev.role() != device::Role::Anchor {
+            return ExitCode::InvalidRole;
+        }
+        dev.relocate(pos, timestamp);
+        self.remove_device_links(id);
+        if let Some(monitor) = self.health.as_mut() {
+            monitor.forget(id);
+            monitor.watch(id, timestamp);
+        }
+        ExitCode::Ok
+    }
+
+    /// Reset tag position, eg. when it's placed at known point
+    pub fn set_tag_position(&mut self, id: DevId, pos: Coords, timestamp: Timestamp) -> ExitCode {
+        if !is_finite(&pos) {
+            return ExitCode::InvalidArgument;
+        }
+        match self.device_mut(id) {
+            Some(dev) if dev.role() == device::Role::Tag => {
+                dev.relocate(pos, timestamp);
+                ExitCode::Ok
+            }
+            Some(_) => ExitCode::InvalidRole,
+            None => ExitCode::UnknownDevice,
+        }
+    }
+
+    pub fn update_device_metadata(&mut self, id: DevId, metadata: device::Metadata) -> ExitCode {
+        match self.device_mut(id) {
+            Some(dev) => {
+                dev.set_metadata(metadata);
+                ExitCode::Ok
+            }
+            None => ExitCode::UnknownDevice,
+        }
+    }
+
+    pub fn get_device(&self, id: DevId) -> Option<&device::Data> {
+        self.index.get(&id).map(|&idx| &self.devices[idx])
+    }
+
+    pub fn contains_device(&self, id: DevId) -> bool {
+        self.get_device(id).is_some()
+    }
+
+    pub fn device_ids(&self) -> Vec<DevId> {
+        self.devices.iter().map(|x| x.id()).collect()
+    }
+
+    fn ensure_device(&mut self, id: DevId, allow_dev_creation: bool) -> ExitCode {
+        if self.index.contains_key(&id) {
+            ExitCode::Ok
+        } else if allow_dev_creation {
+            info!("New device {}", id);
+            self.add_new_device(id)
+        } else {
+            ExitCode::UnknownDevice
+        }
+    }
+
+    /// Newest measure timestamp seen by zone
+    pub fn latest_timestamp(&self) -> Timestamp {
+        self.latest
+    }
+
+    fn solve_context(&self) -> SolveContext<'_> {
+        SolveContext {
+            devices: &self.devices,
+            index: &self.index,
+            measures: &self.measures,
+            adjacency: &self.adjacency,
+            tracker: &self.tracker,
+            map: self.map.as_ref(),
+            gating: self.gating.as_ref(),
+            cooperative: self.cooperative.as_ref(),
+            inertial: &self.inertial,
+            fingerprint: &self.fingerprint,
+            fingerprints: self.fingerprints.as_ref(),
+        }
+    }
+
+    /// Calculate new positions of given tags. Every tag is solved against
+    /// the same zone state, so parallel and sequential results are equal.
+    fn update_dev_positions(&mut self, jobs: &[(DevId, Timestamp)]) {
+        let jobs: Vec<(usize, Timestamp)> = jobs
+            .iter()
+            .filter_map(|(id, ts)| self.index.get(id).map(|&idx| (idx, *ts)))
+            .filter(|&(idx, _)| self.devices[idx].role() == device::Role::Tag)
+            .collect();
+        let ctx = self.solve_context();
+        let mut results: Vec<Solution> = if self.parallel {
+            jobs.par_iter()
+                .map(|&(idx, ts)| ctx.solve(idx, ts))
+                .collect()
+        } else {
+            jobs.iter().map(|&(idx, ts)| ctx.solve(idx, ts)).collect()
+        };
+        ctx.cooperate(&jobs, &mut results);
+        for (&(idx, _), solution) in jobs.iter().zip(results.iter_mut()) {
+            ctx.gate(idx, solution);
+        }
+        let keep = self.gating.as_ref().map_or(0, |g| g.resume_fixes);
+        for (&(idx, ts), solution) in jobs.iter().zip(results) {
+            // reference pressure of the time job is solved at
+            let baro_reference = self.baro_reference(ts);
+            let Solution {
+                mut pos,
+                raw,
+                state,
+                fixed,
+                outcome,
+                ranges,
+                sigma,
+                fingerprint,
+            } = solution;
+            let dev = &mut self.devices[idx];
+            if !fixed && !dev.has_fix() {
+                // still acquiring, nothing to report
+                continue;
+            }
+            if sigma.is_some() {
+                dev.set_position_sigma(sigma);
+            }
+            let id = dev.id();
+            let was_moving = dev.is_moving();
+            let mut calibrated = None;
+            let event = match outcome {
+                gating::Outcome::Accepted => {
+                    if let (true, Some(last)) = (fixed, dev.recent_position(0).copied()) {
+                        let moved = [
+                            raw.coords[0] - last.coords[0],
+                            raw.coords[1] - last.coords[1],
+                        ];
+                        dev.inertial_state_mut().calibrate(&self.inertial, moved);
+                    }
+                    if let (true, Some(config), Some(reference)) =
+                        (fixed, self.barometer.as_ref(), baro_reference)
+                    {
+                        let baro = dev.baro_state_mut();
+                        if let Some(raw) = baro.raw_height(config, reference, pos.timestamp) {
+                            calibrated = baro.calibrate(config, raw, pos.coords[2]);
+                            if let Some(z) = baro.height(config, reference, pos.timestamp) {
+                                pos.coords[2] = config.fuse(pos.coords[2], z);
+                            }
+                        }
+                    }
+                    if let (true, Some(config)) = (fixed, self.stationary.as_ref()) {
+                        pos.coords = dev.motion_state_mut().update(config, pos.coords, &ranges);
+                    }
+                    dev.save_position(pos);
+                    dev.set_track_state(state);
+                    dev.gate_state_mut().accept();
+                    if fixed {
+                        dev.mark_fixed();
+                    }
+                    None
+                }
+                gating::Outcome::Rejected { speed, accel }
+                | gating::Outcome::Clamped { speed, accel } => {
+                    // tracker memory stays, it would follow the outlier
+                    dev.save_position(pos);
+                    dev.gate_state_mut().reject(raw, keep);
+                    let clamped = matches!(outcome, gating::Outcome::Clamped { .. });
+                    info!("Implausible fix of {}, {} m/s {} m/s2", id, speed, accel);
+                    Some(Event::FixRejected {
+                        zone: self.id,
+                        id,
+                        speed,
+                        accel,
+                        clamped,
+                    })
+                }
+                gating::Outcome::Reinitialised => {
+                    info!("Track of {} reinitialised", id);
+                    dev.relocate(pos.coords, pos.timestamp);
+                    Some(Event::TrackReinitialised { zone: self.id, id })
+                }
+            };
+            let dev = &mut self.devices[idx];
+            // steps are part of the new position now
+            if fixed {
+                dev.inertial_state_mut().fixed(pos.timestamp);
+            } else {
+                dev.inertial_state_mut().clear();
+            }
+            let parked = !was_moving && !dev.is_moving();
+            if fixed && outcome == gating::Outcome::Accepted {
+                // fingerprint fix can't judge anchor ranges, anchors are
+                // only marked as seen
+                self.update_anchor_health(&raw, &ranges, !fingerprint);
+            }
+            if let Some(event) = event {
+                self.emit(event);
+            }
+            if let Some(value) = calibrated {
+                info!("Barometer of {} calibrated, offset {}", id, value);
+                self.emit(Event::Calibrated {
+                    zone: self.id,
+                    id,
+                    parameter: BARO_OFFSET.to_string(),
+                    value,
+                });
+            }
+            if parked {
+                // frozen position, nothing new to report
+                if let Some(history) = self.history.as_mut() {
+                    history.record(id, pos);
+                }
+                continue;
+            }
+            self.report_position(id, pos);
+        }
+    }
+
+    /// Publish new position of device
+    fn report_position(&mut self, id: DevId, pos: Trace) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(id, pos);
+        }
+        self.emit(Event::PositionUpdated {
+            zone: self.id,
+            id,
+            pos,
+        });
+        self.update_geofences(id, &pos.coords);
+    }
+
+    /// Fuse barometric height of tags, `None` disables it
+    pub fn set_barometer(&mut self, barometer: Option<baro::Config>) -> ExitCode {
+        if barometer.as_ref().is_some_and(|b| !b.is_valid()) {
+            return ExitCode::InvalidArgument;
+        }
+        self.barometer = barometer;
+        self.reference_pressure = None;
+        ExitCode::Ok
+    }
+
+    pub fn barometer(&self) -> Option<&baro::Config> {
+        self.barometer.as_ref()
+    }
+
+    /// Fresh reference pressure with height of its anchor
+    fn baro_reference(&self, timestamp: Timestamp) -> Option<(f32, f32)> {
+        let config = self.barometer.as_ref()?;
+        let (pressure, at) = self.reference_pressure?;
+        if timestamp.saturating_sub(at) > config.max_age {
+            return None;
+        }
+        let anchor = self.get_device(config.reference?)?;
+        Some((pressure, anchor.estimate_position(at).coords[2]))
+    }
+
+    /// Ingest pressure read by tag or by the reference anchor
+    pub fn add_pressure(&mut self, meas: &measure::Pressure) -> ExitCode {
+        if !meas.pressure.is_finite() || meas.pressure <= 0.0 {
+            return ExitCode::InvalidArgument;
+        }
+        let reference = self.barometer.as_ref().and_then(|b| b.reference);
+        let dev = match self.get_device(meas.id) {
+            Some(d) => d,
+            None => return ExitCode::UnknownDevice,
+        };
+        match dev.role() {
+            device::Role::Anchor if reference == Some(meas.id) => {
+                self.reference_pressure = Some((meas.pressure, meas.timestamp));
+            }
+            device::Role::Anchor => return ExitCode::InvalidRole,
+            device::Role::Tag => {
+                let dev = self.device_mut(meas.id).unwrap();
+                dev.baro_state_mut()
+                    .set_pressure(meas.pressure, meas.timestamp);
+            }
+        }
+        self.touch_device(meas.id, meas.timestamp);
+        ExitCode::Ok
+    }
+
+    /// Set barometer offset of tag placed at known height
+    pub fn calibrate_barometer(&mut self, id: DevId, z: f32, timestamp: Timestamp) -> ExitCode {
+        let (config, reference) = match (self.barometer.clone(), self.baro_reference(timestamp)) {
+            (Some(c), Some(r)) => (c, r),
+            _ => return ExitCode::InvalidArgument,
+        };
+        let dev = match self.device_mut(id) {
+            Some(d) if d.role() == device::Role::Tag => d,
+            Some(_) => return ExitCode::InvalidRole,
+            None => return ExitCode::UnknownDevice,
+        };
+        let raw = match dev.baro_state().raw_height(&config, reference, timestamp) {
+            Some(h) => h,
+            None => return ExitCode::InvalidArgument,
+        };
+        let value = raw - z;
+        dev.baro_state_mut().set_offset(value);
+        self.emit(Event::Calibrated {
+            zone: self.id,
+            id,
+            parameter: BARO_OFFSET.to_string(),
+            value,
+        });
+        ExitCode::Ok
+    }
+
+    /// Locate tags from fingerprint database, `None` disables it
+    pub fn set_fingerprints(&mut self, fingerprints: Option<fingerprint::Database>) {
+        self.fingerprints = fingerprints;
+    }
+
+    pub fn fingerprints(&self) -> Option<&fingerprint::Database> {
+        self.fingerprints.as_ref()
+    }
+
+    pub fn set_fingerprint_config(&mut self, config: fingerprint::Config) -> ExitCode {
+        if !config.is_valid() {
+            return ExitCode::InvalidArgument;
+        }
+        self.fingerprint = config;
+        ExitCode::Ok
+    }
+
+    pub fn fingerprint_config(&self) -> &fingerprint::Config {
+        &self.fingerprint
+    }
+
+    /// Fresh values of tag in kind kept by database
+    fn signal_values(&self, id: DevId, timestamp: Timestamp) -> BTreeMap<DevId, f32> {
+        let max_age = self.fingerprint.max_age;
+        let fresh = |ts: Timestamp| timestamp.saturating_sub(ts) <= max_age;
+        match self.fingerprints.as_ref().map(|db| db.signal) {
+            Some(fingerprint::Signal::Rssi) => self
+                .rssi
+                .get(&id)
+                .into_iter()
+                .flatten()
+                .filter(|(_, (_, ts))| fresh(*ts))
+                .map(|(&anchor, &(rssi, _))| (anchor, rssi))
+                .collect(),
+            Some(fingerprint::Signal::Range) => self
+                .solve_context()
+                .ranges(id, timestamp)
+                .into_iter()
+                .filter(|(anchor, _)| {
+                    let m = self.measures.get(&link_key(id, *anchor));
+                    m.is_some_and(|m| fresh(m.last_timestamp()))
+                })
+                .map(|(anchor, r)| (anchor, r.distance))
+                .collect(),
+            None => BTreeMap::new(),
+        }
+    }
+
+    /// Survey point, current values of tag placed at `pos` are added to
+    /// the database
+    pub fn record_fingerprint(&mut self, id: DevId, pos: Coords, timestamp: Timestamp) -> ExitCode {
+        match self.get_device(id) {
+            Some(dev) if dev.role() == device::Role::Tag => (),
+            Some(_) => return ExitCode::InvalidRole,
+            None => return ExitCode::UnknownDevice,
+        }
+        if !is_finite(&pos) {
+            return ExitCode::InvalidArgument;
+        }
+        let values = self.signal_values(id, timestamp);
+        match self.fingerprints.as_mut() {
+            Some(db) if !values.is_empty() => {
+                info!("Fingerprint of {} recorded at {:?}", id, pos.0);
+                db.record(pos, values);
+                ExitCode::Ok
+            }
+            _ => ExitCode::InvalidArgument,
+        }
+    }
+
+    /// Ingest anchor signal strength seen by tag, tag is located when
+    /// database keeps RSSI
+    pub fn add_rssi(&mut self, meas: &measure::Rssi) -> ExitCode {
+        if !meas.rssi.is_finite() {
+            return ExitCode::InvalidArgument;
+        }
+        match self.get_device(meas.id) {
+            Some(dev) if dev.role() == device::Role::Tag => (),
+            Some(_) => return ExitCode::InvalidRole,
+            None => return ExitCode::UnknownDevice,
+        }
+        match self.get_device(meas.anchor) {
+            Some(dev) if dev.role() == device::Role::Anchor => (),
+            Some(_) => return ExitCode::InvalidRole,
+            None => return ExitCode::UnknownDevice,
+        }
+        self.touch_device(meas.id, meas.timestamp);
+        self.rssi
+            .entry(meas.id)
+            .or_default()
+            .insert(meas.anchor, (meas.rssi, meas.timestamp));
+        self.latest = max(self.latest, meas.timestamp);
+        let db = match self.fingerprints.as_ref() {
+            Some(db) if db.signal == fingerprint::Signal::Rssi => db,
+            _ => return ExitCode::Ok,
+        };
+        let values = self.signal_values(meas.id, meas.timestamp);
+        let fix = match db.locate(&self.fingerprint, &values) {
+            Some(c) => c,
+            None => return ExitCode::Ok,
+        };
+        let sigma = self.fingerprint.sigma;
+        let idx = self.index[&meas.id];
+        let dev = &self.devices[idx];
+        let prev = dev.estimate_position(meas.timestamp);
+        let track = if dev.has_fix() {
+            dev.track_state()
+        } else {
+            &tracker::State::None
+        };
+        let (pos, state) = self.tracker.update_fix(
+            meas.id,
+            &prev,
+            track,
+            &fix,
+            sigma,
+            self.map.as_ref(),
+            meas.timestamp,
+        );
+        let dev = &mut self.devices[idx];
+        dev.save_position(pos);
+        dev.set_track_state(state);
+        dev.set_position_sigma(Some(sigma));
+        dev.mark_fixed();
+        // signal strengths are no ranges, anchors are only marked as seen
+        let seen: Vec<(DevId, f32)> = values.into_iter().collect();
+        self.update_anchor_health(&pos, &seen, false);
+        self.report_position(meas.id, pos);
+        ExitCode::Ok
+    }
+
+    /// Inertial sensors tuning
+    pub fn set_inertial(&mut self, config: inertial::Config) {
+        self.inertial = config;
+    }
+
+    pub fn inertial(&self) -> &inertial::Config {
+        &self.inertial
+    }
+
+    /// Ingest tag inertial reading. Steps are fused with the next fix from
+    /// ranges, or move the tag alone when ranges are missing.
+    pub fn add_inertial(&mut self, meas: &measure::Inertial) -> ExitCode {
+        let id = meas.id();
+        match self.get_device(id) {
+            Some(dev) if dev.role() == device::Role::Tag => (),
+            Some(_) => return ExitCode::InvalidRole,
+            None => return ExitCode::UnknownDevice,
+        }
+        self.touch_device(id, meas.timestamp());
+        let dev = self.device_mut(id).unwrap();
+        match *meas {
+            measure::Inertial::Heading { heading, .. } if heading.is_finite() => {
+                dev.inertial_state_mut().set_heading(heading);
+                ExitCode::Ok
+            }
+            measure::Inertial::Step {
+                length, timestamp, ..
+            } if length.is_finite() && length >= 0.0 => {
+                if dev.inertial_state_mut().step(length) {
+                    self.dead_reckon(id, timestamp);
+                }
+                ExitCode::Ok
+            }
+            _ => ExitCode::InvalidArgument,
+        }
+    }
 
-impl Zone {
-    pub fn new(id: u32) -> Zone {
-        let zone = Zone {
-            id: id,
-            measures: Vec::new(),
-            devices: Vec::new(),
+    /// Move device along its steps when ranges are missing for a while
+    fn dead_reckon(&mut self, id: DevId, timestamp: Timestamp) {
+        let config = &self.inertial;
+        let dev = match self.index.get(&id) {
+            Some(&idx) => &mut self.devices[idx],
+            None => return,
         };
-        zone
+        if !dev.has_fix() || !dev.inertial_state().in_outage(config, timestamp) {
+            return;
+        }
+        let motion = match dev.inertial_state().pending(config) {
+            Some(m) => m,
+            None => return,
+        };
+        let last = dev.estimate_position(timestamp);
+        let pos = Trace {
+            coords: motion.predict(&last.coords),
+            timestamp,
+        };
+        let mut state = dev.track_state().clone();
+        state.transform(&|c| motion.predict(c));
+        dev.set_track_state(state);
+        dev.save_position(pos);
+        dev.inertial_state_mut().clear();
+        self.report_position(id, pos);
     }
 
-    pub fn add_device(&mut self, id: DevId, pos: [i32; 3]) -> ExitCode {
-        let count = self.devices.iter().filter(|x| x.id() == id).count();
-        assert_eq!(count, 0);
-        let dev = device::Data::new_with_pos(id, pos);
-        self.devices.push(dev);
+    /// Solve independent devices in parallel
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    /// Check measure can be stored, without storing it
+    fn check_measure(&self, meas: &measure::Distance, allow_dev_creation: bool) -> ExitCode {
+        let [id1, id2] = meas.id;
+        if id1 == id2 || !meas.distance.is_finite() || meas.distance < 0.0 {
+            return ExitCode::InvalidArgument;
+        }
+        if !allow_dev_creation && meas.id.iter().any(|i| !self.index.contains_key(i)) {
+            return ExitCode::UnknownDevice;
+        }
         ExitCode::Ok
     }
 
-    fn calc_dev_position(&self, dev: &device::Data, timestamp: Timestamp) -> Trace {
-        let measures: Vec<&measure::List> = self
-            .measures
-            .iter()
-            .filter(|&x| (x.id(0) == dev.id() || x.id(1) == dev.id()))
-            .collect();
-        let connected_devices_id: Vec<u32> = measures
-            .iter()
-            .map(|x| {
-                if x.id(0) == dev.id() {
-                    x.id(1)
-                } else {
-                    x.id(0)
-                }
-            })
-            .collect();
-        let devices: Vec<&device::Data> = self
-            .devices
-            .iter()
-            .filter(|&x| connected_devices_id.iter().any(|&v| v == x.id()))
-            .collect();
-        let pos = dev.calc_position(&measures, &devices, timestamp);
-        pos
+    /// Store measure without recalculating positions
+    fn store_measure(&mut self, meas: &measure::Distance, allow_dev_creation: bool) -> ExitCode {
+        let ret = self.check_measure(meas, allow_dev_creation);
+        if ret != ExitCode::Ok {
+            return ret;
+        }
+        let [id1, id2] = meas.id;
+        for &i in meas.id.iter() {
+            let ret = self.ensure_device(i, allow_dev_creation);
+            if ret != ExitCode::Ok {
+                return ret;
+            }
+            self.touch_device(i, meas.timestamp);
+        }
+        self.latest = max(self.latest, meas.timestamp);
+        let key = link_key(id1, id2);
+        let meas = measure::Distance::new([key.0, key.1], meas.timestamp, meas.distance);
+        match self.measures.get_mut(&key) {
+            Some(l) => {
+                trace!("Update measure {}-{} {}", key.0, key.1, meas.distance);
+                l.update(meas);
+            }
+            None => {
+                info!("New connection {}-{} {}!", key.0, key.1, meas.distance);
+                self.measures.insert(key, measure::List::new(meas));
+                self.adjacency.entry(key.0).or_default().insert(key.1);
+                self.adjacency.entry(key.1).or_default().insert(key.0);
+                let ids = [key.0, key.1];
+                self.emit(Event::NewLink { zone: self.id, ids });
+            }
+        }
+        ExitCode::Ok
     }
 
-    fn update_dev_position(
+    /// Ingest set of measures and calculate position of every affected
+    /// device once, using all of them. Returns first error met, valid
+    /// measures are processed anyway.
+    pub fn add_measures(
         &mut self,
-        id: DevId,
-        timestamp: Timestamp,
+        measures: &[measure::Distance],
         allow_dev_creation: bool,
     ) -> ExitCode {
-        let dev_index = match self.devices.iter().position(|x| x.id() == id) {
-            Some(idx) => idx,
-            None => {
-                if allow_dev_creation {
-                    let ret = self.add_device(id, [0, 0, 0]);
-                    if ret != ExitCode::Ok {
-                        return ret;
-                    }
-                    self.devices.len() - 1
-                } else {
-                    return ExitCode::UnknownDevice;
+        let mut result = ExitCode::Ok;
+        // affected devices with their freshest measure timestamp
+        let mut dirty: BTreeMap<DevId, Timestamp> = BTreeMap::new();
+        for meas in measures.iter() {
+            let ret = self.store_measure(meas, allow_dev_creation);
+            if ret != ExitCode::Ok {
+                if result == ExitCode::Ok {
+                    result = ret;
                 }
+                continue;
             }
-        };
-        let pos = self.calc_dev_position(&self.devices[dev_index], timestamp);
-        self.devices[dev_index].save_position(pos);
-        ExitCode::Ok
+            for &i in meas.id.iter() {
+                let ts = dirty.entry(i).or_insert(meas.timestamp);
+                *ts = max(*ts, meas.timestamp);
+            }
+        }
+        let jobs: Vec<(DevId, Timestamp)> = dirty.into_iter().collect();
+        self.update_dev_positions(&jobs);
+        result
+    }
+
+    /// Group measures into epochs of given length [ms], positions are
+    /// calculated once per epoch. `None` processes every measure at once.
+    pub fn set_epoch_window(&mut self, window: Option<Timestamp>) {
+        self.epoch_window = window;
+        if window.is_none() {
+            self.flush_epoch();
+        }
+    }
+
+    /// Process measures collected in current epoch
+    pub fn flush_epoch(&mut self) -> ExitCode {
+        let epoch = std::mem::take(&mut self.epoch);
+        let mut result = ExitCode::Ok;
+        // Runs sharing the same creation flag are batched, so arrival order
+        // is kept across the whole epoch
+        let mut start = 0;
+        while start < epoch.len() {
+            let allow_dev_creation = epoch[start].1;
+            let end = epoch[start..]
+                .iter()
+                .position(|(_, allow)| *allow != allow_dev_creation)
+                .map_or(epoch.len(), |len| start + len);
+            let batch: Vec<measure::Distance> = epoch[start..end].iter().map(|(m, _)| *m).collect();
+            let ret = self.add_measures(&batch, allow_dev_creation);
+            if result == ExitCode::Ok {
+                result = ret;
+            }
+            start = end;
+        }
+        result
+    }
+
+    /// Process current epoch once its window has passed at `now`
+    pub fn flush_expired_epoch(&mut self, now: Timestamp) -> ExitCode {
+        match (self.epoch_window, self.epoch.first()) {
+            (Some(window), Some((first, _))) if now >= first.timestamp + window => {
+                self.flush_epoch()
+            }
+            _ => ExitCode::Ok,
+        }
     }
 
+    /// Measure is validated at once, in epoch mode it's processed when the
+    /// epoch is flushed
     pub fn add_measure(
         &mut self,
         id1: DevId,
@@ -98,50 +1427,153 @@ impl Zone {
         timestamp: Timestamp,
         allow_dev_creation: bool,
     ) -> ExitCode {
-        let id = [min(id1, id2), max(id1, id2)];
-        let meas = measure::Distance::new([id[0], id[1]], timestamp, distance);
-        let ml = self
-            .measures
-            .iter_mut()
-            .find(|x| x.id(0) == id[0] && x.id(1) == id[1]);
-        match ml {
-            Some(l) => {
-                l.update(meas);
-                trace!("Update measure {}-{} {}", id[0], id[1], distance);
-                for &i in id.iter() {
-                    let ret = self.update_dev_position(i, timestamp, allow_dev_creation);
-                    if ret != ExitCode::Ok {
-                        return ret;
-                    }
-                }
-            }
-            None => {
-                info!("New connection {}-{} {}!", id[0], id[1], distance);
-                let new_ml = measure::List::new(meas);
-                self.measures.push(new_ml);
+        let meas = measure::Distance::new([id1, id2], timestamp, distance);
+        if self.epoch_window.is_none() {
+            return self.add_measures(&[meas], allow_dev_creation);
+        }
+        let ret = self.check_measure(&meas, allow_dev_creation);
+        if ret != ExitCode::Ok {
+            return ret;
+        }
+        let ret = self.flush_expired_epoch(timestamp);
+        self.epoch.push((meas, allow_dev_creation));
+        ret
+    }
+
+    /// Save whole zone state, see `snapshot` module
+    pub fn snapshot(&self) -> snapshot::Snapshot {
+        let mut links: Vec<(&LinkKey, &measure::List)> = self.measures.iter().collect();
+        links.sort_by_key(|(key, _)| **key);
+        snapshot::Snapshot {
+            version: snapshot::VERSION,
+            config: snapshot::Config {
+                id: self.id,
+                geo_ref: self.geo_ref,
+                map: self.map.clone(),
+                tracker: self.tracker.clone(),
+                liveness: self.liveness,
+                epoch_window: self.epoch_window,
+                parallel: self.parallel,
+                placement: self.placement,
+                boundary: self.boundary.clone(),
+                geofences: self.geofences.clone(),
+                gating: self.gating.clone(),
+                stationary: self.stationary.clone(),
+                cooperative: self.cooperative.clone(),
+                inertial: self.inertial.clone(),
+                barometer: self.barometer.clone(),
+                health: self.health.as_ref().map(|m| m.config().clone()),
+                fingerprint: self.fingerprint.clone(),
+                fingerprints: self.fingerprints.clone(),
+            },
+            devices: self.devices.clone(),
+            links: links.into_iter().map(|(_, list)| list.clone()).collect(),
+            epoch: self.epoch.clone(),
+        }
+    }
+
+    /// Recreate zone from saved state, settings are checked as by their
+    /// setters
+    pub fn restore(snapshot: snapshot::Snapshot) -> Result<Zone, snapshot::Error> {
+        let config = snapshot.config;
+        let mut zone = Zone::new(config.id);
+        zone.geo_ref = config.geo_ref;
+        zone.map = config.map;
+        if zone.set_tracker(config.tracker) != ExitCode::Ok {
+            return Err(snapshot::Error::Config("tracker"));
+        }
+        zone.liveness = config.liveness;
+        zone.epoch_window = config.epoch_window;
+        zone.parallel = config.parallel;
+        zone.placement = config.placement;
+        zone.boundary = config.boundary;
+        zone.geofences = config.geofences;
+        zone.set_gating(config.gating);
+        zone.stationary = config.stationary;
+        zone.cooperative = config.cooperative;
+        zone.inertial = config.inertial;
+        if zone.set_barometer(config.barometer) != ExitCode::Ok {
+            return Err(snapshot::Error::Config("barometer"));
+        }
+        zone.health = config.health.map(health::Monitor::new);
+        if zone.set_fingerprint_config(config.fingerprint) != ExitCode::Ok {
+            return Err(snapshot::Error::Config("fingerprint"));
+        }
+        zone.fingerprints = config.fingerprints;
+        for dev in snapshot.devices.into_iter() {
+            zone.latest = max(zone.latest, dev.last_activity());
+            let id = dev.id();
+            let ret = zone.insert_device(dev);
+            if ret != ExitCode::Ok {
+                return Err(snapshot::Error::Device(id, ret));
             }
         }
-        ExitCode::Ok
+        for list in snapshot.links.into_iter() {
+            let key = link_key(list.id(0), list.id(1));
+            zone.adjacency.entry(key.0).or_default().insert(key.1);
+            zone.adjacency.entry(key.1).or_default().insert(key.0);
+            zone.measures.insert(key, list);
+        }
+        zone.epoch = snapshot.epoch;
+        Ok(zone)
+    }
+
+    fn describe(&self, dev: &device::Data, timestamp: Timestamp) -> device::Description {
+        device::Description::new(dev, timestamp)
+            .with_geo(self.geo_ref.as_ref())
+            .with_liveness(dev.liveness(timestamp, &self.liveness))
+            .with_zone(self.id)
+            .with_floor(self.floor(dev, timestamp))
     }
 
-    pub fn get_device_ptr(&self, id: DevId) -> *const device::Data {
-        let dev = self.devices.iter().find(|x| x.id() == id);
-        dev.unwrap()
+    /// Floor of tag with calibrated barometer
+    fn floor(&self, dev: &device::Data, timestamp: Timestamp) -> Option<i32> {
+        let config = self.barometer.as_ref()?;
+        dev.baro_state().offset()?;
+        Some(config.floor(dev.estimate_position(timestamp).coords[2]))
     }
 
     pub fn get_dev_position(&self, id: DevId, timestamp: Timestamp) -> Option<device::Description> {
-        let dev = match self.devices.iter().find(|d| d.id() == id) {
-            Some(d) => d,
-            None => return None,
-        };
-        Some(device::Description::new(dev, timestamp))
+        let dev = self.get_device(id)?;
+        Some(self.describe(dev, timestamp))
     }
 
     pub fn get_all_devices_position(&mut self, timestamp: Timestamp) -> Vec<device::Description> {
-        let mut pos: Vec<device::Description> = Vec::new();
-        pos.reserve(self.devices.len());
+        self.get_devices_position(timestamp, false)
+    }
+
+    /// Positions of devices, optionally without the lost ones. Epoch
+    /// finished by `timestamp` is processed first.
+    pub fn get_devices_position(
+        &mut self,
+        timestamp: Timestamp,
+        exclude_lost: bool,
+    ) -> Vec<device::Description> {
+        self.flush_expired_epoch(timestamp);
+        let mut pos: Vec<device::Description> = Vec::with_capacity(self.devices.len());
+        for dev in self.devices.iter() {
+            let desc = self.describe(dev, timestamp);
+            if exclude_lost && desc.liveness == device::Liveness::Lost {
+                continue;
+            }
+            pos.push(desc);
+        }
+        pos
+    }
+
+    /// Positions of devices at current clock time, tags are extrapolated
+    /// along their recent movement
+    pub fn get_devices_position_now(&mut self, exclude_lost: bool) -> Vec<device::Description> {
+        let now = self.now();
+        self.flush_expired_epoch(now);
+        let mut pos: Vec<device::Description> = Vec::with_capacity(self.devices.len());
         for dev in self.devices.iter() {
-            pos.push(device::Description::new(dev, timestamp));
+            let mut desc = self.describe(dev, now);
+            if exclude_lost && desc.liveness == device::Liveness::Lost {
+                continue;
+            }
+            desc.pos = dev.extrapolate_position(now);
+            pos.push(desc.with_geo(self.geo_ref.as_ref()));
         }
         pos
     }
@@ -165,5 +1597,748 @@ mod tests {
         assert!(v[0].pos.coords[1] < v[0].pos.coords[2]);
         assert!(v[1].pos.coords[0] > v[1].pos.coords[1]);
         assert!(v[1].pos.coords[1] > v[1].pos.coords[2]);
+        assert!(v[0].geo.is_none());
+    }
+
+    #[test]
+    fn geo_reference_in_description() {
+        let mut zone = Zone::new(1);
+        zone.add_device(1, [10, 0, 0]);
+        zone.set_geo_reference(Some(geo::Reference::new(50.0, 20.0, 200.0, 0.0)));
+        let desc = zone.get_dev_position(1, 0).unwrap();
+        let pos = desc.geo.unwrap();
+        assert!(pos.lat > 50.0);
+        assert!((pos.lon - 20.0).abs() < 1e-9);
+        let local = zone.from_wgs84(&pos).unwrap();
+        assert!((local[0] - 10.0).abs() < 1e-3);
+    }
+
+    fn add_square_anchors(zone: &mut Zone) {
+        let anchors = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0],
+            [10.0, 10.0, 0.0],
+        ];
+        for (i, a) in anchors.iter().enumerate() {
+            assert_eq!(zone.add_anchor(100 + i as DevId, Coords(*a)), ExitCode::Ok);
+        }
+    }
+
+    fn range_tag(zone: &mut Zone, id: DevId, pos: [f32; 3], timestamp: Timestamp) {
+        for i in 0..4 {
+            let anchor = zone
+                .get_dev_position(100 + i, timestamp)
+                .unwrap()
+                .pos
+                .coords;
+            let d = ((anchor[0] - pos[0]).powi(2) + (anchor[1] - pos[1]).powi(2)).sqrt();
+            assert_eq!(
+                zone.add_measure(id, 100 + i, d, timestamp, true),
+                ExitCode::Ok
+            );
+        }
+    }
+
+    #[test]
+    fn tag_position_tracked() {
+        let mut zone = Zone::new(1);
+        add_square_anchors(&mut zone);
+        for ts in 0..3 {
+            range_tag(&mut zone, 1, [3.0, 7.0, 0.0], ts);
+        }
+        let desc = zone.get_dev_position(1, 3).unwrap();
+        assert!((desc.pos.coords[0] - 3.0).abs() < 0.01);
+        assert!((desc.pos.coords[1] - 7.0).abs() < 0.01);
+        // anchors stay in place
+        let desc = zone.get_dev_position(101, 3).unwrap();
+        assert_eq!(desc.pos.coords[0], 10.0);
+    }
+
+    #[test]
+    fn particle_tracker_selected_per_zone() {
+        let mut zone = Zone::new(1);
+        let config = tracker::particle::Config {
+            seed: Some(3),
+            ..Default::default()
+        };
+        let invalid = tracker::particle::Config {
+            range_sigma: 0.0,
+            ..config.clone()
+        };
+        assert_eq!(
+            zone.set_tracker(tracker::Tracker::Particle(invalid)),
+            ExitCode::InvalidArgument
+        );
+        assert_eq!(
+            zone.set_tracker(tracker::Tracker::Particle(config)),
+            ExitCode::Ok
+        );
+        add_square_anchors(&mut zone);
+        for ts in 0..10 {
+            range_tag(&mut zone, 1, [6.0, 2.0, 0.0], ts * 100);
+        }
+        let desc = zone.get_dev_position(1, 1000).unwrap();
+        assert!((desc.pos.coords[0] - 6.0).abs() < 0.3);
+        assert!((desc.pos.coords[1] - 2.0).abs() < 0.3);
+    }
+
+    #[test]
+    fn map_constrains_track() {
+        let mut zone = Zone::new(1);
+        let mut obstacles = map::ObstacleMap::new();
+        obstacles
+            .walls
+            .push(map::Segment::new([3.0, -100.0], [3.0, 100.0]));
+        zone.set_map(Some(obstacles));
+        zone.add_device(1, [0, 0, 0]);
+        zone.add_device(2, [10, 0, 0]);
+        for ts in 0..20 {
+            assert_eq!(zone.add_measure(1, 2, 5.0, ts, false), ExitCode::Ok);
+        }
+        for id in [1, 2].iter() {
+            let desc = zone.get_dev_position(*id, 20).unwrap();
+            assert!(desc.pos.coords[0] < 3.0 || desc.pos.coords[0] > 9.0);
+        }
+    }
+
+    #[test]
+    fn map_stops_ranged_tag_at_wall() {
+        let mut zone = Zone::new(1);
+        let mut obstacles = map::ObstacleMap::new();
+        obstacles
+            .walls
+            .push(map::Segment::new([3.0, -100.0], [3.0, 100.0]));
+        zone.set_map(Some(obstacles));
+        add_square_anchors(&mut zone);
+        zone.add_device(1, [1, 5, 0]);
+        for ts in 0..5 {
+            range_tag(&mut zone, 1, [8.0, 5.0, 0.0], ts);
+        }
+        let desc = zone.get_dev_position(1, 5).unwrap();
+        assert!(desc.pos.coords[0] < 3.0);
+    }
+
+    #[test]
+    fn lost_and_recovered_devices() {
+        let mut zone = Zone::new(1);
+        zone.set_liveness_timeouts(device::LivenessTimeouts {
+            stale: 100,
+            lost: 1000,
+        });
+        add_square_anchors(&mut zone);
+        range_tag(&mut zone, 1, [3.0, 3.0, 0.0], 0);
+        range_tag(&mut zone, 1, [3.0, 3.0, 0.0], 10);
+        assert!(zone.check_liveness(50).is_empty());
+        let desc = zone.get_dev_position(1, 500).unwrap();
+        assert_eq!(desc.liveness, device::Liveness::Stale);
+        assert_eq!(desc.timestamp, 10);
+
+        // silent anchors are not reported
+        let events = zone.check_liveness(2000);
+        assert_eq!(events, vec![LivenessEvent::Lost(1)]);
+        assert!(zone.check_liveness(3000).is_empty());
+        assert_eq!(zone.get_devices_position(3000, true).len(), 4);
+        assert_eq!(zone.get_devices_position(3000, false).len(), 5);
+
+        range_tag(&mut zone, 1, [3.0, 3.0, 0.0], 3000);
+        let events = zone.check_liveness(3000);
+        assert_eq!(events, vec![LivenessEvent::Recovered(1)]);
+        let active = zone.get_devices_position(3000, true);
+        assert_eq!(active.len(), 5);
+
+        // activity of device never measured starts at its registration
+        assert_eq!(zone.add_device(2, [0, 0, 0]), ExitCode::Ok);
+        assert!(zone.check_liveness(3500).is_empty());
+    }
+
+    #[test]
+    fn device_lifecycle() {
+        let mut zone = Zone::new(1);
+        add_square_anchors(&mut zone);
+        assert_eq!(zone.add_device(1, [0, 0, 0]), ExitCode::Ok);
+        assert_eq!(zone.add_device(1, [0, 0, 0]), ExitCode::AlreadyExist);
+        assert_eq!(
+            zone.add_anchor(100, Coords([1.0, 1.0, 1.0])),
+            ExitCode::AlreadyExist
+        );
+        assert_eq!(
+            zone.add_measure(1, 1, 1.0, 0, true),
+            ExitCode::InvalidArgument
+        );
+        assert_eq!(
+            zone.add_measure(1, 100, f32::NAN, 0, true),
+            ExitCode::InvalidArgument
+        );
+        range_tag(&mut zone, 1, [3.0, 3.0, 0.0], 0);
+
+        let metadata = device::Metadata {
+            name: Some("forklift 1".to_string()),
+            class: Some("forklift".to_string()),
+        };
+        assert_eq!(
+            zone.update_device_metadata(1, metadata.clone()),
+            ExitCode::Ok
+        );
+        assert_eq!(zone.get_device(1).unwrap().metadata(), &metadata);
+
+        let pos = Coords([20.0, 0.0, 0.0]);
+        assert_eq!(zone.move_anchor(1, pos, 1), ExitCode::InvalidRole);
+        assert_eq!(zone.move_anchor(101, pos, 1), ExitCode::Ok);
+        assert_eq!(zone.get_dev_position(101, 1).unwrap().pos.coords[0], 20.0);
+        assert_eq!(zone.remove_link(1, 101), ExitCode::UnknownLink);
+        assert_eq!(zone.remove_link(1, 100), ExitCode::Ok);
+
+        assert_eq!(zone.remove_device(1), ExitCode::Ok);
+        assert_eq!(zone.remove_device(1), ExitCode::UnknownDevice);
+        assert!(zone.get_device(1).is_none());
+        assert_eq!(zone.remove_device_links(102), 0);
+        assert_eq!(zone.device_ids(), vec![100, 101, 102, 103]);
+    }
+
+    fn square_ranges(id: DevId, pos: [f32; 3], timestamp: Timestamp) -> Vec<measure::Distance> {
+        let anchors = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0], [10.0, 10.0]];
+        anchors
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let d = ((a[0] - pos[0]).powi(2) + (a[1] - pos[1]).powi(2)).sqrt();
+                measure::Distance::new([id, 100 + i as DevId], timestamp, d)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batch_solves_with_first_measures() {
+        let mut zone = Zone::new(1);
+        add_square_anchors(&mut zone);
+        let batch = square_ranges(1, [2.0, 6.0, 0.0], 10);
+        assert_eq!(zone.add_measures(&batch, false), ExitCode::UnknownDevice);
+        assert!(zone.get_device(1).is_none());
+        assert_eq!(zone.add_measures(&batch, true), ExitCode::Ok);
+        let desc = zone.get_dev_position(1, 10).unwrap();
+        assert!((desc.pos.coords[0] - 2.0).abs() < 0.01);
+        assert!((desc.pos.coords[1] - 6.0).abs() < 0.01);
+        assert_eq!(desc.pos.timestamp, 10);
+    }
+
+    #[test]
+    fn epoch_window_groups_measures() {
+        let mut zone = Zone::new(1);
+        add_square_anchors(&mut zone);
+        zone.set_epoch_window(Some(50));
+        for (i, m) in square_ranges(1, [2.0, 6.0, 0.0], 0).iter().enumerate() {
+            let ts = i as Timestamp * 10;
+            assert_eq!(
+                zone.add_measure(m.id[0], m.id[1], m.distance, ts, true),
+                ExitCode::Ok
+            );
+        }
+        assert!(zone.get_device(1).is_none());
+        // measure out of the window closes the epoch
+        assert_eq!(zone.add_measure(1, 100, 1.0, 60, true), ExitCode::Ok);
+        let desc = zone.get_dev_position(1, 60).unwrap();
+        assert!((desc.pos.coords[0] - 2.0).abs() < 0.01);
+        // invalid measure is refused before buffering
+        assert_eq!(
+            zone.add_measure(2, 100, 1.0, 70, false),
+            ExitCode::UnknownDevice
+        );
+        // last epoch is processed once its window passes, with no traffic
+        assert_eq!(zone.get_devices_position(110, false).len(), 5);
+        let moved = zone.get_dev_position(1, 60).unwrap();
+        assert!((moved.pos.coords[0] - desc.pos.coords[0]).abs() > 0.01);
+    }
+
+    #[test]
+    fn epoch_keeps_arrival_order() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut zone = Zone::new(1);
+        zone.subscribe(Box::new(tx));
+        add_square_anchors(&mut zone);
+        zone.set_epoch_window(Some(50));
+        assert_eq!(zone.add_measure(1, 100, 2.0, 0, true), ExitCode::Ok);
+        assert_eq!(zone.add_measure(101, 102, 10.0, 10, false), ExitCode::Ok);
+        assert_eq!(zone.add_measure(1, 101, 8.0, 20, true), ExitCode::Ok);
+        assert_eq!(zone.flush_epoch(), ExitCode::Ok);
+        let links: Vec<[DevId; 2]> = rx
+            .try_iter()
+            .filter_map(|e| match e {
+                Event::NewLink { ids, .. } => Some(ids),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(links, vec![[1, 100], [101, 102], [1, 101]]);
+    }
+
+    #[test]
+    fn parallel_solve_equals_sequential() {
+        let run = |parallel: bool| {
+            let mut zone = Zone::new(1);
+            let config = tracker::particle::Config {
+                particles: 100,
+                seed: Some(11),
+                ..Default::default()
+            };
+            zone.set_tracker(tracker::Tracker::Particle(config));
+            zone.set_parallel(parallel);
+            add_square_anchors(&mut zone);
+            for ts in 0..5 {
+                let mut batch = Vec::new();
+                for tag in 1..20 {
+                    let pos = [tag as f32 * 0.4, 10.0 - tag as f32 * 0.3, 0.0];
+                    batch.extend(square_ranges(tag, pos, ts * 100));
+                }
+                assert_eq!(zone.add_measures(&batch, true), ExitCode::Ok);
+            }
+            zone.get_all_devices_position(500)
+        };
+        let sequential = run(false);
+        let parallel = run(true);
+        assert_eq!(sequential.len(), parallel.len());
+        for (s, p) in sequential.iter().zip(parallel.iter()) {
+            assert_eq!(s.id, p.id);
+            assert_eq!(s.pos.coords.0, p.pos.coords.0);
+        }
+    }
+
+    #[test]
+    fn manual_clock_drives_liveness_and_extrapolation() {
+        let clock = crate::clock::ManualClock::new(0);
+        let mut zone = Zone::new(1);
+        zone.set_clock(Arc::new(clock.clone()));
+        add_square_anchors(&mut zone);
+        range_tag(&mut zone, 1, [2.0, 5.0, 0.0], 1000);
+        range_tag(&mut zone, 1, [3.0, 5.0, 0.0], 2000);
+        clock.set(2500);
+        let pos = zone.get_devices_position_now(false);
+        let tag = pos.iter().find(|d| d.id == 1).unwrap();
+        assert_eq!(tag.pos.timestamp, 2500);
+        assert!((tag.pos.coords[0] - 3.5).abs() < 0.05);
+        assert!(zone.check_liveness_now().is_empty());
+        clock.advance(60_000);
+        let events = zone.check_liveness_now();
+        assert!(events.contains(&LivenessEvent::Lost(1)));
+    }
+
+    #[test]
+    fn subscriber_receives_events() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut zone = Zone::new(4);
+        zone.subscribe(Box::new(tx));
+        zone.set_geofences(vec![Geofence::new(
+            "dock",
+            map::Polygon::new(vec![[0.0, 0.0], [4.0, 0.0], [4.0, 10.0], [0.0, 10.0]]),
+        )]);
+        add_square_anchors(&mut zone);
+        zone.add_measures(&square_ranges(1, [2.0, 5.0, 0.0], 100), true);
+        zone.add_measures(&square_ranges(1, [7.0, 5.0, 0.0], 200), true);
+        zone.check_liveness(100_000);
+        let events: Vec<Event> = rx.try_iter().collect();
+        let created = Event::DeviceCreated {
+            zone: 4,
+            id: 1,
+            role: device::Role::Tag,
+        };
+        assert!(events.contains(&created));
+        assert!(events.contains(&Event::NewLink {
+            zone: 4,
+            ids: [1, 100]
+        }));
+        let updates = events
+            .iter()
+            .filter(|e| matches!(e, Event::PositionUpdated { id: 1, .. }))
+            .count();
+        assert_eq!(updates, 2);
+        let fence = "dock".to_string();
+        let entered = events.iter().position(|e| {
+            *e == Event::GeofenceEntered {
+                zone: 4,
+                id: 1,
+                fence: fence.clone(),
+            }
+        });
+        let left = events.iter().position(|e| {
+            *e == Event::GeofenceLeft {
+                zone: 4,
+                id: 1,
+                fence: fence.clone(),
+            }
+        });
+        assert!(entered.unwrap() < left.unwrap());
+        assert!(events.contains(&Event::DeviceLost { zone: 4, id: 1 }));
+    }
+
+    #[test]
+    fn history_keeps_tag_trajectory() {
+        let mut zone = Zone::new(1);
+        zone.set_history_duration(Some(60_000));
+        add_square_anchors(&mut zone);
+        for i in 0..5 {
+            let x = 2.0 + i as f32;
+            zone.add_measures(&square_ranges(1, [x, 5.0, 0.0], i * 1000), true);
+        }
+        let history = zone.history().unwrap();
+        assert_eq!(history.devices(), vec![1]);
+        assert_eq!(history.range(1, 1000, 3000).len(), 3);
+        let pos = history.at(1, 2500).unwrap();
+        assert!((pos.coords[0] - 4.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn implausible_jump_gated_until_track_resumes() {
+        let mut zone = Zone::new(1);
+        let mut classes = BTreeMap::new();
+        let limits = gating::MotionLimits {
+            max_speed: 2.0,
+            max_accel: 50.0,
+        };
+        classes.insert("worker".to_string(), limits);
+        zone.set_gating(Some(gating::Config {
+            classes,
+            ..gating::Config::default()
+        }));
+        let (tx, rx) = std::sync::mpsc::channel();
+        zone.subscribe(Box::new(tx));
+        add_square_anchors(&mut zone);
+        zone.add_measures(&square_ranges(1, [2.0, 5.0, 0.0], 0), true);
+        let metadata = device::Metadata {
+            class: Some("worker".to_string()),
+            ..device::Metadata::default()
+        };
+        zone.update_device_metadata(1, metadata);
+        zone.add_measures(&square_ranges(1, [2.1, 5.0, 0.0], 100), true);
+        // 6 m in 100 ms
+        zone.add_measures(&square_ranges(1, [8.0, 5.0, 0.0], 200), true);
+        let pos = zone.get_dev_position(1, 200).unwrap().pos;
+        assert!((pos.coords[0] - 2.1).abs() < 0.05);
+        assert_eq!(zone.get_device(1).unwrap().rejected_fixes(), 1);
+        let rejected = rx
+            .try_iter()
+            .filter(|e| matches!(e, Event::FixRejected { id: 1, .. }))
+            .count();
+        assert_eq!(rejected, 1);
+        zone.add_measures(&square_ranges(1, [8.1, 5.0, 0.0], 300), true);
+        zone.add_measures(&square_ranges(1, [8.2, 5.0, 0.0], 400), true);
+        let pos = zone.get_dev_position(1, 400).unwrap().pos;
+        assert!((pos.coords[0] - 8.2).abs() < 0.05);
+        assert!(rx
+            .try_iter()
+            .any(|e| e == Event::TrackReinitialised { zone: 1, id: 1 }));
+    }
+
+    #[test]
+    fn parked_tag_frozen_until_it_moves() {
+        let mut zone = Zone::new(1);
+        zone.set_stationary(Some(stationary::Config::default()));
+        add_square_anchors(&mut zone);
+        let (tx, rx) = std::sync::mpsc::channel();
+        zone.subscribe(Box::new(tx));
+        for i in 0..20 {
+            let jitter = if i % 2 == 0 { 0.05 } else { -0.05 };
+            zone.add_measures(&square_ranges(1, [4.0 + jitter, 5.0, 0.0], i * 100), true);
+        }
+        let desc = zone.get_dev_position(1, 2000).unwrap();
+        assert!(!desc.moving);
+        assert!((desc.pos.coords[0] - 4.0).abs() < 0.03);
+        let updates = rx
+            .try_iter()
+            .filter(|e| matches!(e, Event::PositionUpdated { .. }))
+            .count();
+        assert!(updates < 20);
+        zone.add_measures(&square_ranges(1, [5.0, 5.0, 0.0], 2000), true);
+        let desc = zone.get_dev_position(1, 2000).unwrap();
+        assert!(desc.moving);
+        assert!((desc.pos.coords[0] - 5.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn new_tag_acquired_once_solvable() {
+        let mut zone = Zone::new(1);
+        let anchors = [[10.0, 10.0, 0.0], [20.0, 10.0, 0.0], [10.0, 20.0, 0.0]];
+        for (i, a) in anchors.iter().enumerate() {
+            zone.add_anchor(100 + i as DevId, Coords(*a));
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        zone.subscribe(Box::new(tx));
+        let target = [25.0, 25.0];
+        for (i, a) in anchors.iter().enumerate() {
+            let d = ((a[0] - target[0]).powi(2) + (a[1] - target[1]).powi(2)).sqrt();
+            zone.add_measure(1, 100 + i as DevId, d, 10, true);
+            let acquired = rx
+                .try_iter()
+                .any(|e| matches!(e, Event::PositionUpdated { id: 1, .. }));
+            assert_eq!(acquired, i == 2);
+        }
+        let pos = zone.get_dev_position(1, 10).unwrap().pos;
+        assert!((pos.coords[0] - target[0]).abs() < 0.05);
+        assert!((pos.coords[1] - target[1]).abs() < 0.05);
+    }
+
+    #[test]
+    fn edge_tag_borrows_geometry_from_peer() {
+        let mut zone = Zone::new(1);
+        zone.set_cooperative(Some(tracker::cooperative::Config::default()));
+        add_square_anchors(&mut zone);
+        zone.add_device(2, [11, 4, 0]);
+        let (inner, edge) = ([5.0, 5.0], [12.0f32, 5.0]);
+        let mut batch = square_ranges(1, [inner[0], inner[1], 0.0], 100);
+        for &(anchor, pos) in [(101, [10.0, 0.0]), (103, [10.0, 10.0])].iter() {
+            let d = (edge[0] - pos[0]).hypot(edge[1] - pos[1]);
+            batch.push(measure::Distance::new([2, anchor], 100, d));
+        }
+        batch.push(measure::Distance::new([1, 2], 100, 7.0));
+        assert_eq!(zone.add_measures(&batch, true), ExitCode::Ok);
+        let pos = zone.get_dev_position(2, 100).unwrap().pos;
+        assert!((pos.coords[0] - edge[0]).abs() < 0.1);
+        assert!((pos.coords[1] - edge[1]).abs() < 0.1);
+        assert!(zone.get_device(2).unwrap().position_sigma().is_some());
+    }
+
+    #[test]
+    fn displaced_anchor_reported() {
+        let mut zone = Zone::new(1);
+        zone.set_anchor_health(Some(health::Config::default()));
+        add_square_anchors(&mut zone);
+        let (tx, rx) = std::sync::mpsc::channel();
+        zone.subscribe(Box::new(tx));
+        // anchor 103 configured at [10, 10], but bumped away
+        let real = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0], [10.0, 12.0]];
+        for i in 0..40 {
+            let angle = i as f32 * 0.7;
+            let pos = [5.0 + 3.0 * angle.cos(), 5.0 + 3.0 * angle.sin()];
+            let batch: Vec<measure::Distance> = real
+                .iter()
+                .enumerate()
+                .map(|(k, a)| {
+                    let d = (a[0] - pos[0]).hypot(a[1] - pos[1]);
+                    measure::Distance::new([1, 100 + k as DevId], i * 100, d)
+                })
+                .collect();
+            zone.add_measures(&batch, true);
+        }
+        let monitor = zone.anchor_health().unwrap();
+        assert_eq!(monitor.status(103), Some(health::Status::Biased));
+        let offset = rx
+            .try_iter()
+            .find_map(|e| match e {
+                Event::AnchorMoved {
+                    id: 103, offset, ..
+                } => Some(offset),
+                _ => None,
+            })
+            .unwrap();
+        assert!(offset[1] > 0.5);
+    }
+
+    #[test]
+    fn steps_carry_tag_through_ranging_outage() {
+        let mut zone = Zone::new(1);
+        add_square_anchors(&mut zone);
+        zone.add_measures(&square_ranges(1, [2.0, 5.0, 0.0], 0), true);
+        let heading = measure::Inertial::Heading {
+            id: 1,
+            timestamp: 0,
+            heading: 0.0,
+        };
+        assert_eq!(zone.add_inertial(&heading), ExitCode::Ok);
+        // no ranges, tag walks along x axis
+        for i in 1..=4 {
+            let step = measure::Inertial::Step {
+                id: 1,
+                timestamp: 1000 + i * 500,
+                length: 0.5,
+            };
+            assert_eq!(zone.add_inertial(&step), ExitCode::Ok);
+        }
+        let pos = zone.get_dev_position(1, 3000).unwrap().pos;
+        assert!((pos.coords[0] - 4.0).abs() < 0.05);
+        // steps between fixes are blended with ranges
+        let step = measure::Inertial::Step {
+            id: 1,
+            timestamp: 3100,
+            length: 0.5,
+        };
+        zone.add_inertial(&step);
+        zone.add_measures(&square_ranges(1, [4.5, 5.0, 0.0], 3200), true);
+        let pos = zone.get_dev_position(1, 3200).unwrap().pos;
+        assert!((pos.coords[0] - 4.5).abs() < 0.05);
+        let anchor = measure::Inertial::Heading {
+            id: 100,
+            timestamp: 0,
+            heading: 0.0,
+        };
+        assert_eq!(zone.add_inertial(&anchor), ExitCode::InvalidRole);
+    }
+
+    #[test]
+    fn barometer_lifts_tag_to_upper_floor() {
+        let mut zone = Zone::new(1);
+        let config = baro::Config {
+            reference: Some(100),
+            ..baro::Config::default()
+        };
+        let invalid = baro::Config {
+            range_sigma: f32::NAN,
+            ..config.clone()
+        };
+        assert_eq!(zone.set_barometer(Some(invalid)), ExitCode::InvalidArgument);
+        assert_eq!(zone.set_barometer(Some(config)), ExitCode::Ok);
+        add_square_anchors(&mut zone);
+        let (tx, rx) = std::sync::mpsc::channel();
+        zone.subscribe(Box::new(tx));
+        zone.add_measures(&square_ranges(1, [5.0, 5.0, 0.0], 0), true);
+        let pressure = |id, timestamp, height: f32| measure::Pressure {
+            id,
+            timestamp,
+            pressure: 101_325.0 * (1.0 - height / 44_330.8).powf(1.0 / 0.190_263),
+        };
+        assert_eq!(zone.add_pressure(&pressure(100, 0, 0.0)), ExitCode::Ok);
+        // tag barometer reads 5 m too high
+        assert_eq!(zone.add_pressure(&pressure(1, 0, 5.0)), ExitCode::Ok);
+        assert_eq!(zone.calibrate_barometer(1, 0.0, 0), ExitCode::Ok);
+        assert!(rx.try_iter().any(|e| matches!(e,
+            Event::Calibrated { id: 1, value, .. } if (value - 5.0).abs() < 0.1)));
+        assert_eq!(zone.get_dev_position(1, 0).unwrap().floor, Some(0));
+        for i in 1..5 {
+            zone.add_pressure(&pressure(100, i * 100, 0.0));
+            zone.add_pressure(&pressure(1, i * 100, 9.0));
+            zone.add_measures(&square_ranges(1, [5.0, 5.0, 0.0], i * 100), true);
+        }
+        let desc = zone.get_dev_position(1, 400).unwrap();
+        assert!((desc.pos.coords[2] - 4.0).abs() < 0.3);
+        assert_eq!(desc.floor, Some(1));
+    }
+
+    #[test]
+    fn tag_located_from_surveyed_rssi() {
+        let mut zone = Zone::new(1);
+        add_square_anchors(&mut zone);
+        zone.set_fingerprints(Some(fingerprint::Database::new(fingerprint::Signal::Rssi)));
+        let anchors = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0], [10.0, 10.0]];
+        let seen = |zone: &mut Zone, id, pos: [f32; 2], timestamp| {
+            for (i, a) in anchors.iter().enumerate() {
+                let d = ((a[0] - pos[0]).powi(2) + (a[1] - pos[1]).powi(2)).sqrt();
+                let meas = measure::Rssi {
+                    id,
+                    anchor: 100 + i as DevId,
+                    timestamp,
+                    rssi: -40.0 - 20.0 * d.max(0.5).log10(),
+                };
+                assert_eq!(zone.add_rssi(&meas), ExitCode::Ok);
+            }
+        };
+        assert_eq!(zone.add_new_device(1), ExitCode::Ok);
+        for x in 0..6 {
+            for y in 0..6 {
+                let pos = [x as f32 * 2.0, y as f32 * 2.0];
+                seen(&mut zone, 1, pos, 0);
+                let surveyed = Coords([pos[0], pos[1], 0.0]);
+                assert_eq!(zone.record_fingerprint(1, surveyed, 0), ExitCode::Ok);
+            }
+        }
+        assert_eq!(zone.fingerprints().unwrap().len(), 36);
+        assert_eq!(zone.add_new_device(2), ExitCode::Ok);
+        seen(&mut zone, 2, [3.0, 7.0], 100);
+        let desc = zone.get_dev_position(2, 100).unwrap();
+        assert!((desc.pos.coords[0] - 3.0).abs() < 1.0);
+        assert!((desc.pos.coords[1] - 7.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn silent_anchor_reported_missing() {
+        let mut zone = Zone::new(1);
+        zone.set_anchor_health(Some(health::Config {
+            missing_timeout: 1000,
+            ..health::Config::default()
+        }));
+        add_square_anchors(&mut zone);
+        assert_eq!(zone.add_anchor(104, Coords([5.0, 5.0, 3.0])), ExitCode::Ok);
+        let (tx, rx) = std::sync::mpsc::channel();
+        zone.subscribe(Box::new(tx));
+        for ts in 0..8 {
+            let batch = square_ranges(1, [4.0, 6.0, 0.0], ts * 200);
+            assert_eq!(zone.add_measures(&batch, true), ExitCode::Ok);
+        }
+        zone.check_liveness(1500);
+        let missing: Vec<DevId> = rx
+            .try_iter()
+            .filter_map(|e| match e {
+                Event::AnchorStatus {
+                    id,
+                    status: health::Status::Missing,
+                    ..
+                } => Some(id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(missing, vec![104]);
+        let monitor = zone.anchor_health().unwrap();
+        assert_eq!(monitor.status(104), Some(health::Status::Missing));
+    }
+
+    #[test]
+    fn fingerprint_fix_feeds_tracker() {
+        let mut zone = Zone::new(1);
+        let config = tracker::particle::Config {
+            particles: 100,
+            seed: Some(2),
+            ..Default::default()
+        };
+        zone.set_tracker(tracker::Tracker::Particle(config));
+        zone.set_anchor_health(Some(health::Config {
+            missing_timeout: 1000,
+            ..health::Config::default()
+        }));
+        add_square_anchors(&mut zone);
+        // only anchors 100 and 101 reach the surveyed area
+        let mut db = fingerprint::Database::new(fingerprint::Signal::Range);
+        for x in 0..6 {
+            for y in 0..6 {
+                let pos = [x as f32 * 2.0, y as f32 * 2.0, 0.0];
+                let values = square_ranges(1, pos, 0)
+                    .iter()
+                    .take(2)
+                    .map(|m| (m.id[1], m.distance))
+                    .collect();
+                db.record(Coords(pos), values);
+            }
+        }
+        zone.set_fingerprints(Some(db));
+        let config = fingerprint::Config {
+            sigma: -1.0,
+            ..fingerprint::Config::default()
+        };
+        assert_eq!(
+            zone.set_fingerprint_config(config),
+            ExitCode::InvalidArgument
+        );
+        let (tx, rx) = std::sync::mpsc::channel();
+        zone.subscribe(Box::new(tx));
+        let batch = square_ranges(1, [4.0, 6.0, 0.0], 0);
+        assert_eq!(zone.add_measures(&batch, true), ExitCode::Ok);
+        // tag 2 is located from fingerprints alone, 102 and 103 go silent
+        for i in 1..8 {
+            let batch = square_ranges(2, [4.0, 6.0, 0.0], i * 200);
+            assert_eq!(zone.add_measures(&batch[..2], true), ExitCode::Ok);
+        }
+        let dev = zone.get_device(2).unwrap();
+        assert!(matches!(dev.track_state(), tracker::State::Particles(_)));
+        let desc = zone.get_dev_position(2, 1400).unwrap();
+        assert!((desc.pos.coords[0] - 4.0).abs() < 1.0);
+        assert!((desc.pos.coords[1] - 6.0).abs() < 1.0);
+        zone.check_liveness(1500);
+        let missing: Vec<DevId> = rx
+            .try_iter()
+            .filter_map(|e| match e {
+                Event::AnchorStatus {
+                    id,
+                    status: health::Status::Missing,
+                    ..
+                } => Some(id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(missing, vec![102, 103]);
     }
 }
@@ -9,6 +9,7 @@ use env_logger::Env;
 use log::{error, info};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 use websocket::sync::Server;
 
 mod ws_handler;
@@ -16,6 +17,11 @@ mod zone_wrapper;
 
 pub use crate::zone_wrapper::messages::*;
 
+// positions of all devices are published this often [ms]
+const PUBLISH_INTERVAL: u64 = 1000;
+// trajectories are kept for last hour [ms]
+pub const HISTORY_DURATION: u64 = 3_600_000;
+
 fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info"))
         .format_timestamp(None)
@@ -43,15 +49,34 @@ fn main() {
     thread::Builder::new()
         .name("engine".to_string())
         .spawn(move || {
-            // use only single zone at this moment
+            // zone 0 is the default one, next zones are added on request
+            let mut manager = engine::manager::ZoneManager::new();
             let mut zone = engine::zone::Zone::new(0);
+            zone.set_parallel(true);
+            zone.set_history_duration(Some(HISTORY_DURATION));
+            manager.add_zone(zone);
+            let (event_putter, event_reader) = mpsc::channel::<engine::event::Event>();
+            manager.subscribe(Box::new(event_putter));
+            let mut publisher = engine::clock::Ticker::new(PUBLISH_INTERVAL);
             loop {
-                let msg = engine_cmd_reader.recv().unwrap();
-                let response = zone_wrapper::parse(&mut zone, msg);
-                match response {
-                    Some(r) => dispatcher_cmd_putter.send(r).unwrap(),
-                    None => (),
-                };
+                match engine_cmd_reader.recv_timeout(Duration::from_millis(PUBLISH_INTERVAL)) {
+                    Ok(msg) => {
+                        if let Some(r) = zone_wrapper::parse(&mut manager, msg) {
+                            dispatcher_cmd_putter.send(r).unwrap();
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                if publisher.due(manager.now()) {
+                    if let Some(r) = zone_wrapper::publish(&mut manager) {
+                        dispatcher_cmd_putter.send(r).unwrap();
+                    }
+                }
+                let events: Vec<engine::event::Event> = event_reader.try_iter().collect();
+                if let Some(r) = zone_wrapper::publish_events(&events) {
+                    dispatcher_cmd_putter.send(r).unwrap();
+                }
             }
         })
         .unwrap();
@@ -3,10 +3,11 @@
 //
 
 use super::messages::*;
+use engine::zone::ExitCode;
 use log::{error, info};
 
 fn parse_dev_wake_up(
-    zone: &mut engine::zone::Zone,
+    manager: &mut engine::manager::ZoneManager,
     msg: serde_json::Value,
     _sender: &SharedSender,
 ) -> Result<Option<MessageTarget>, MessageFormat> {
@@ -17,13 +18,25 @@ fn parse_dev_wake_up(
             return Err(MessageFormat::Text("Invalid id".to_string()));
         }
     };
-    zone.add_device(id, [0, 0, 0]);
-    let msg = MessageFormat::Text("Added new device".to_string());
-    return Ok(Some(MessageTarget::WebData(msg)));
+    let zone = match manager.select_zone_mut(super::msg_zone(&msg)) {
+        Some(z) => z,
+        None => return Err(MessageFormat::Text("Unknown zone".to_string())),
+    };
+    match zone.add_new_device(id) {
+        ExitCode::Ok => {
+            let msg = MessageFormat::Text("Added new device".to_string());
+            Ok(Some(MessageTarget::WebData(msg)))
+        }
+        ExitCode::AlreadyExist => Ok(None),
+        ret => Err(MessageFormat::Text(format!(
+            "Device registration failed, {:?}",
+            ret
+        ))),
+    }
 }
 
 fn process_json(
-    zone: &mut engine::zone::Zone,
+    manager: &mut engine::manager::ZoneManager,
     mut msg: serde_json::Value,
     sender: &SharedSender,
 ) -> Result<Option<MessageTarget>, MessageFormat> {
@@ -40,13 +53,13 @@ fn process_json(
 }
 
 pub fn parse(
-    zone: &mut engine::zone::Zone,
+    manager: &mut engine::manager::ZoneManager,
     cmd: &MessageFormat,
     sender: &SharedSender,
 ) -> Option<MessageTarget> {
     match cmd {
         MessageFormat::Text(txt) => {
-            info!("zone {} received dev txt cmd {}", zone.id, txt);
+            info!("received dev txt cmd {}", txt);
             let json: serde_json::Value = match serde_json::from_str(txt) {
                 Ok(j) => j,
                 Err(e) => {
@@ -55,12 +68,12 @@ pub fn parse(
                     return Some(MessageTarget::Direct(msg, sender.clone()));
                 }
             };
-            match process_json(zone, json, sender) {
+            match process_json(manager, json, sender) {
                 Ok(_) => (),
                 Err(e) => println!("json parse failed"),
             }
         }
-        MessageFormat::Bin(bin) => info!("zone {} received dev bin cmd {:?}", zone.id, bin),
+        MessageFormat::Bin(bin) => info!("received dev bin cmd {:?}", bin),
     }
     Some(MessageTarget::Direct(
         MessageFormat::Text("Yes, sir!".to_string()),
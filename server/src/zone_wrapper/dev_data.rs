@@ -4,12 +4,14 @@
 
 use super::dev_data_msg::{DevDataDistMeasure, DevDataMsgType};
 use super::messages::*;
+use engine::utils::{parse_timestamp, ZoneId};
 use engine::zone::ExitCode;
 use log::{error, info, trace};
 use num_traits::FromPrimitive;
 
 fn process_dist_measure(
-    zone: &mut engine::zone::Zone,
+    manager: &mut engine::manager::ZoneManager,
+    zone: Option<ZoneId>,
     msg: serde_json::Value,
     _sender: &SharedSender,
 ) -> Result<Option<MessageTarget>, MessageFormat> {
@@ -20,12 +22,16 @@ fn process_dist_measure(
             return Err(MessageFormat::Text(msg));
         }
     };
-    let ret = zone.add_measure(m.id[0], m.id[1], m.distance, m.timestamp, true);
+    let timestamp = parse_timestamp(m.timestamp, manager.latest_timestamp());
+    let ret = manager.add_measure(zone, m.id[0], m.id[1], m.distance, timestamp, true);
+    for handover in manager.take_handovers() {
+        info!("handover {:?}", handover);
+    }
     match ret {
         ExitCode::Ok => {
             let mut desc_list: Vec<engine::device::Description> = Vec::new();
             for dev_id in [m.id[0], m.id[1]].iter() {
-                match zone.get_dev_position(*dev_id, m.timestamp) {
+                match manager.get_dev_position(*dev_id, timestamp) {
                     Some(dec_desc) => desc_list.push(dec_desc),
                     None => (),
                 };
@@ -42,11 +48,155 @@ fn process_dist_measure(
     }
 }
 
+fn process_dist_measure_batch(
+    manager: &mut engine::manager::ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let batch: Vec<DevDataDistMeasure> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            let msg = "Invalid distance batch message format!".to_string();
+            return Err(MessageFormat::Text(msg));
+        }
+    };
+    let reference = manager.latest_timestamp();
+    let measures: Vec<engine::measure::Distance> = batch
+        .iter()
+        .map(|m| {
+            let timestamp = parse_timestamp(m.timestamp, reference);
+            engine::measure::Distance::new(m.id, timestamp, m.distance)
+        })
+        .collect();
+    let ret = manager.add_measures(zone, &measures, true);
+    if ret != ExitCode::Ok {
+        error!("batch processing error, {:?}", ret);
+    }
+    for handover in manager.take_handovers() {
+        info!("handover {:?}", handover);
+    }
+    let mut ids: Vec<u32> = batch.iter().flat_map(|m| m.id.iter().cloned()).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let timestamp = measures.iter().map(|m| m.timestamp).max().unwrap_or(0);
+    let desc_list: Vec<engine::device::Description> = ids
+        .iter()
+        .filter_map(|id| manager.get_dev_position(*id, timestamp))
+        .collect();
+    let msg = MessageFormat::Text(serde_json::to_string(&desc_list).unwrap());
+    Ok(Some(MessageTarget::WebData(msg)))
+}
+
+fn process_inertial(
+    manager: &mut engine::manager::ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let mut meas: engine::measure::Inertial = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            let msg = "Invalid inertial message format!".to_string();
+            return Err(MessageFormat::Text(msg));
+        }
+    };
+    let reference = manager.latest_timestamp();
+    let timestamp = match &mut meas {
+        engine::measure::Inertial::Step { timestamp, .. }
+        | engine::measure::Inertial::Heading { timestamp, .. } => timestamp,
+    };
+    *timestamp = parse_timestamp(*timestamp, reference);
+    match manager.add_inertial(zone, &meas) {
+        ExitCode::Ok => Ok(None),
+        ret => Err(MessageFormat::Text(format!(
+            "Inertial processing failed, {:?}",
+            ret
+        ))),
+    }
+}
+
+fn process_pressure(
+    manager: &mut engine::manager::ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let mut meas: engine::measure::Pressure = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            let msg = "Invalid pressure message format!".to_string();
+            return Err(MessageFormat::Text(msg));
+        }
+    };
+    meas.timestamp = parse_timestamp(meas.timestamp, manager.latest_timestamp());
+    match manager.add_pressure(zone, &meas) {
+        ExitCode::Ok => Ok(None),
+        ret => Err(MessageFormat::Text(format!(
+            "Pressure processing failed, {:?}",
+            ret
+        ))),
+    }
+}
+
+fn process_rssi(
+    manager: &mut engine::manager::ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let mut meas: engine::measure::Rssi = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            let msg = "Invalid RSSI message format!".to_string();
+            return Err(MessageFormat::Text(msg));
+        }
+    };
+    meas.timestamp = parse_timestamp(meas.timestamp, manager.latest_timestamp());
+    match manager.add_rssi(zone, &meas) {
+        ExitCode::Ok => Ok(None),
+        ret => Err(MessageFormat::Text(format!(
+            "RSSI processing failed, {:?}",
+            ret
+        ))),
+    }
+}
+
+fn process_anchor_description(
+    manager: &mut engine::manager::ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let desc: engine::device::Description = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            let msg = "Invalid anchor description format!".to_string();
+            return Err(MessageFormat::Text(msg));
+        }
+    };
+    let zone = match manager.select_zone_mut(desc.zone.or(zone)) {
+        Some(z) => z,
+        None => return Err(MessageFormat::Text("Unknown zone".to_string())),
+    };
+    match zone.add_anchor(desc.id, desc.pos.coords) {
+        ExitCode::Ok => {
+            info!(
+                "zone {} new anchor {} at {:?}",
+                zone.id, desc.id, desc.pos.coords
+            );
+            let desc_list = vec![desc.with_zone(zone.id)];
+            let msg = MessageFormat::Text(serde_json::to_string(&desc_list).unwrap());
+            Ok(Some(MessageTarget::WebData(msg)))
+        }
+        ret => Err(MessageFormat::Text(format!(
+            "Anchor registration failed, {:?}",
+            ret
+        ))),
+    }
+}
+
 fn process_json(
-    zone: &mut engine::zone::Zone,
+    manager: &mut engine::manager::ZoneManager,
     mut msg: serde_json::Value,
     sender: &SharedSender,
 ) -> Result<Option<MessageTarget>, MessageFormat> {
+    let zone = super::msg_zone(&msg);
     let msg_type = match &msg["cmd"] {
         serde_json::Value::Number(n) => n.as_i64().unwrap_or(-1),
         _ => {
@@ -55,20 +205,33 @@ fn process_json(
     };
     match FromPrimitive::from_i64(msg_type) {
         Some(DevDataMsgType::DistMeasure) => {
-            return process_dist_measure(zone, msg["data"].take(), sender)
+            return process_dist_measure(manager, zone, msg["data"].take(), sender)
+        }
+        Some(DevDataMsgType::DistMeasureBatch) => {
+            return process_dist_measure_batch(manager, zone, msg["data"].take())
+        }
+        Some(DevDataMsgType::Inertial) => {
+            return process_inertial(manager, zone, msg["data"].take())
+        }
+        Some(DevDataMsgType::Pressure) => {
+            return process_pressure(manager, zone, msg["data"].take())
+        }
+        Some(DevDataMsgType::Rssi) => return process_rssi(manager, zone, msg["data"].take()),
+        Some(DevDataMsgType::AnchorDescription) => {
+            return process_anchor_description(manager, zone, msg["data"].take())
         }
         _ => return Err(MessageFormat::Text("Unknown message type".to_string())),
     }
 }
 
 pub fn parse(
-    zone: &mut engine::zone::Zone,
+    manager: &mut engine::manager::ZoneManager,
     cmd: &MessageFormat,
     sender: &SharedSender,
 ) -> Option<MessageTarget> {
     match cmd {
         MessageFormat::Text(txt) => {
-            trace!("zone {} received web txt data {}", zone.id, txt);
+            trace!("received dev txt data {}", txt);
             let json: serde_json::Value = match serde_json::from_str(txt) {
                 Ok(j) => j,
                 Err(e) => {
@@ -77,12 +240,12 @@ pub fn parse(
                     return Some(MessageTarget::Direct(msg, sender.clone()));
                 }
             };
-            match process_json(zone, json, sender) {
+            match process_json(manager, json, sender) {
                 Ok(msg) => return msg,
                 Err(msg) => return Some(MessageTarget::Direct(msg, sender.clone())),
             }
         }
-        MessageFormat::Bin(bin) => info!("zone {} received web bin data {:?}", zone.id, bin),
+        MessageFormat::Bin(bin) => info!("received dev bin data {:?}", bin),
     }
     None
 }
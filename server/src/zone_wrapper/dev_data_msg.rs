@@ -4,11 +4,17 @@ use serde_derive::{Deserialize, Serialize};
 #[derive(FromPrimitive)]
 pub enum DevDataMsgType {
     DistMeasure = 1,
+    AnchorDescription = 2,
+    DistMeasureBatch = 3,
+    Inertial = 4,
+    Pressure = 5,
+    Rssi = 6,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct DevDataDistMeasure {
     pub id: [u32; 2],
-    pub timestamp: u32,
+    /// 64-bit milliseconds, legacy 32-bit values are unwrapped on reception
+    pub timestamp: u64,
     pub distance: f32,
 }
@@ -6,24 +6,61 @@
 //
 
 use engine;
+use log::info;
 
 pub mod messages;
 pub use messages::*;
 
 mod dev_data_msg;
+mod web_comm_msg;
 
 mod dev_comm;
 mod dev_data;
 mod web_comm;
 mod web_data;
 
-pub fn parse(zone: &mut engine::zone::Zone, msg: MessageContext) -> Option<MessageTarget> {
+/// Optional `zone` field of JSON message, selects zone explicitly
+fn msg_zone(msg: &serde_json::Value) -> Option<engine::utils::ZoneId> {
+    msg["zone"].as_u64().map(|id| id as engine::utils::ZoneId)
+}
+
+pub fn parse(
+    manager: &mut engine::manager::ZoneManager,
+    msg: MessageContext,
+) -> Option<MessageTarget> {
     let response: Option<MessageTarget>;
     match &msg.data {
-        MessageSource::DevCommand(cmd) => response = dev_comm::parse(zone, cmd, &msg.sender),
-        MessageSource::DevData(cmd) => response = dev_data::parse(zone, cmd, &msg.sender),
-        MessageSource::WebCommand(cmd) => response = web_comm::parse(zone, cmd, &msg.sender),
-        MessageSource::WebData(cmd) => response = web_data::parse(zone, cmd, &msg.sender),
+        MessageSource::DevCommand(cmd) => response = dev_comm::parse(manager, cmd, &msg.sender),
+        MessageSource::DevData(cmd) => response = dev_data::parse(manager, cmd, &msg.sender),
+        MessageSource::WebCommand(cmd) => response = web_comm::parse(manager, cmd, &msg.sender),
+        MessageSource::WebData(cmd) => response = web_data::parse(manager, cmd, &msg.sender),
     }
     response
 }
+
+/// Periodic report, positions of every device at current time
+pub fn publish(manager: &mut engine::manager::ZoneManager) -> Option<MessageTarget> {
+    for (zone_id, event) in manager.check_liveness_now() {
+        info!("zone {} liveness event {:?}", zone_id, event);
+    }
+    let desc_list = manager.get_all_devices_position_now();
+    if desc_list.is_empty() {
+        return None;
+    }
+    let msg = MessageFormat::Text(serde_json::to_string(&desc_list).unwrap());
+    Some(MessageTarget::WebData(msg))
+}
+
+/// Forward engine events to web clients, position updates are already
+/// delivered as device descriptions
+pub fn publish_events(events: &[engine::event::Event]) -> Option<MessageTarget> {
+    let events: Vec<&engine::event::Event> = events
+        .iter()
+        .filter(|e| !matches!(e, engine::event::Event::PositionUpdated { .. }))
+        .collect();
+    if events.is_empty() {
+        return None;
+    }
+    let msg = MessageFormat::Text(serde_json::to_string(&events).unwrap());
+    Some(MessageTarget::WebData(msg))
+}
@@ -3,16 +3,491 @@
 //
 
 use super::messages::*;
-use log::info;
+use super::web_comm_msg::*;
+use engine::manager::ZoneManager;
+use engine::utils::{DevId, ZoneId};
+use engine::zone::{ExitCode, Zone};
+use log::{error, info};
+use num_traits::FromPrimitive;
+
+fn process_set_geo_reference(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    // `null` data removes zone geodetic reference
+    let reference: Option<engine::geo::Reference> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid geo reference message format!".to_string(),
+            ));
+        }
+    };
+    info!("zone {} geo reference set to {:?}", zone.id, reference);
+    zone.set_geo_reference(reference);
+    Ok(None)
+}
+
+fn process_set_obstacle_map(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    // `null` data disables map matching
+    let map: Option<engine::map::ObstacleMap> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid obstacle map message format!".to_string(),
+            ));
+        }
+    };
+    info!("zone {} obstacle map {}", zone.id, map.is_some());
+    zone.set_map(map);
+    Ok(None)
+}
+
+fn process_set_tracker(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let tracker: engine::tracker::Tracker = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid tracker message format!".to_string(),
+            ));
+        }
+    };
+    info!("zone {} tracker set to {:?}", zone.id, tracker);
+    exit_code_response(zone.set_tracker(tracker))
+}
+
+fn exit_code_response(ret: ExitCode) -> Result<Option<MessageTarget>, MessageFormat> {
+    match ret {
+        ExitCode::Ok => Ok(None),
+        _ => Err(MessageFormat::Text(format!("Command failed, {:?}", ret))),
+    }
+}
+
+/// Explicitly selected zone or the one owning the device
+fn device_zone(
+    manager: &mut ZoneManager,
+    zone: Option<ZoneId>,
+    dev: DevId,
+) -> Result<&mut Zone, MessageFormat> {
+    let found = match zone {
+        Some(id) => manager.zone_mut(id),
+        None => manager.device_zone_mut(dev),
+    };
+    found.ok_or_else(|| {
+        MessageFormat::Text(format!("Command failed, {:?}", ExitCode::UnknownDevice))
+    })
+}
+
+fn process_remove_device(
+    manager: &mut ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommDevice = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid device format!".to_string())),
+    };
+    exit_code_response(device_zone(manager, zone, m.id)?.remove_device(m.id))
+}
+
+fn process_update_device_metadata(
+    manager: &mut ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommDeviceMetadata = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid device metadata format!".to_string(),
+            ))
+        }
+    };
+    exit_code_response(device_zone(manager, zone, m.id)?.update_device_metadata(m.id, m.metadata))
+}
+
+fn process_move_anchor(
+    manager: &mut ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommMoveAnchor = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid anchor format!".to_string())),
+    };
+    exit_code_response(device_zone(manager, zone, m.id)?.move_anchor(m.id, m.pos, m.timestamp))
+}
+
+fn process_add_zone(
+    manager: &mut ZoneManager,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommZone = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid zone format!".to_string())),
+    };
+    let mut zone = Zone::new(m.id);
+    zone.set_parallel(true);
+    zone.set_history_duration(Some(crate::HISTORY_DURATION));
+    info!("new zone {}", m.id);
+    exit_code_response(manager.add_zone(zone))
+}
+
+fn process_remove_zone(
+    manager: &mut ZoneManager,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommZone = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid zone format!".to_string())),
+    };
+    match manager.remove_zone(m.id) {
+        Some(_) => Ok(None),
+        None => exit_code_response(ExitCode::UnknownZone),
+    }
+}
+
+fn process_set_zone_area(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommZoneArea = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid zone area format!".to_string())),
+    };
+    info!("zone {} placed at {:?}", zone.id, m.placement);
+    zone.set_placement(m.placement);
+    zone.set_boundary(m.boundary);
+    Ok(None)
+}
+
+fn process_set_geofences(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let fences: Vec<engine::event::Geofence> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid geofences format!".to_string())),
+    };
+    info!("zone {} geofences set, {} areas", zone.id, fences.len());
+    zone.set_geofences(fences);
+    Ok(None)
+}
+
+fn process_set_gating(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let gating: Option<engine::gating::Config> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid gating format!".to_string())),
+    };
+    info!("zone {} gating set to {:?}", zone.id, gating);
+    zone.set_gating(gating);
+    Ok(None)
+}
+
+fn process_set_stationary(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let stationary: Option<engine::stationary::Config> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid stationary detection format!".to_string(),
+            ))
+        }
+    };
+    info!(
+        "zone {} stationary detection set to {:?}",
+        zone.id, stationary
+    );
+    zone.set_stationary(stationary);
+    Ok(None)
+}
+
+fn process_set_cooperative(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let cooperative: Option<engine::tracker::cooperative::Config> =
+        match serde_json::from_value(msg) {
+            Ok(v) => v,
+            Err(_) => {
+                return Err(MessageFormat::Text(
+                    "Invalid cooperative solver format!".to_string(),
+                ))
+            }
+        };
+    info!(
+        "zone {} cooperative solver set to {:?}",
+        zone.id, cooperative
+    );
+    zone.set_cooperative(cooperative);
+    Ok(None)
+}
+
+fn process_set_anchor_health(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let health: Option<engine::health::Config> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid anchor health format!".to_string(),
+            ))
+        }
+    };
+    info!("zone {} anchor health set to {:?}", zone.id, health);
+    zone.set_anchor_health(health);
+    Ok(None)
+}
+
+fn process_get_anchor_health(
+    zone: &mut Zone,
+    sender: &SharedSender,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let report = match zone.anchor_health() {
+        Some(m) => m.report(),
+        None => Vec::new(),
+    };
+    let msg = MessageFormat::Text(serde_json::to_string(&report).unwrap());
+    Ok(Some(MessageTarget::Direct(msg, sender.clone())))
+}
+
+fn process_set_barometer(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let barometer: Option<engine::baro::Config> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid barometer format!".to_string())),
+    };
+    info!("zone {} barometer set to {:?}", zone.id, barometer);
+    exit_code_response(zone.set_barometer(barometer))
+}
+
+fn process_calibrate_barometer(
+    manager: &mut ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommCalibrateBarometer = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid barometer calibration format!".to_string(),
+            ))
+        }
+    };
+    let zone = device_zone(manager, zone, m.id)?;
+    let timestamp = zone.latest_timestamp();
+    exit_code_response(zone.calibrate_barometer(m.id, m.z, timestamp))
+}
+
+fn process_set_fingerprints(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let fingerprints: Option<engine::fingerprint::Database> = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid fingerprint database format!".to_string(),
+            ))
+        }
+    };
+    info!(
+        "zone {} fingerprint database set, {} records",
+        zone.id,
+        fingerprints.as_ref().map_or(0, |db| db.len())
+    );
+    zone.set_fingerprints(fingerprints);
+    Ok(None)
+}
+
+fn process_get_fingerprints(
+    zone: &mut Zone,
+    sender: &SharedSender,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let msg = MessageFormat::Text(serde_json::to_string(&zone.fingerprints()).unwrap());
+    Ok(Some(MessageTarget::Direct(msg, sender.clone())))
+}
+
+fn process_record_fingerprint(
+    manager: &mut ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommRecordFingerprint = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid fingerprint format!".to_string(),
+            ))
+        }
+    };
+    let zone = device_zone(manager, zone, m.id)?;
+    let timestamp = zone.latest_timestamp();
+    exit_code_response(zone.record_fingerprint(m.id, m.pos, timestamp))
+}
+
+fn process_set_fingerprint_config(
+    zone: &mut Zone,
+    msg: serde_json::Value,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let config: engine::fingerprint::Config = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(MessageFormat::Text(
+                "Invalid fingerprint config format!".to_string(),
+            ))
+        }
+    };
+    info!("zone {} fingerprint config set to {:?}", zone.id, config);
+    exit_code_response(zone.set_fingerprint_config(config))
+}
+
+fn process_get_history(
+    manager: &mut ZoneManager,
+    zone: Option<ZoneId>,
+    msg: serde_json::Value,
+    sender: &SharedSender,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let m: WebCommHistory = match serde_json::from_value(msg) {
+        Ok(v) => v,
+        Err(_) => return Err(MessageFormat::Text("Invalid history format!".to_string())),
+    };
+    let zone = device_zone(manager, zone, m.id)?;
+    let mut traces = match zone.history() {
+        Some(h) => h.range(m.id, m.from, m.to),
+        None => Vec::new(),
+    };
+    if let Some(interval) = m.interval {
+        traces = engine::history::downsample(&traces, interval);
+    }
+    let msg = MessageFormat::Text(serde_json::to_string(&traces).unwrap());
+    Ok(Some(MessageTarget::Direct(msg, sender.clone())))
+}
+
+/// Zone selected by message or the default one
+fn selected_zone(
+    manager: &mut ZoneManager,
+    zone: Option<ZoneId>,
+) -> Result<&mut Zone, MessageFormat> {
+    manager
+        .select_zone_mut(zone)
+        .ok_or_else(|| MessageFormat::Text(format!("Command failed, {:?}", ExitCode::UnknownZone)))
+}
+
+fn process_json(
+    manager: &mut ZoneManager,
+    mut msg: serde_json::Value,
+    sender: &SharedSender,
+) -> Result<Option<MessageTarget>, MessageFormat> {
+    let zone = super::msg_zone(&msg);
+    let msg_type = match &msg["cmd"] {
+        serde_json::Value::Number(n) => n.as_i64().unwrap_or(-1),
+        _ => {
+            return Err(MessageFormat::Text("Lack of 'cmd' field".to_string()));
+        }
+    };
+    match FromPrimitive::from_i64(msg_type) {
+        Some(WebCommMsgType::SetGeoReference) => {
+            process_set_geo_reference(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetObstacleMap) => {
+            process_set_obstacle_map(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetTracker) => {
+            process_set_tracker(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::RemoveDevice) => {
+            process_remove_device(manager, zone, msg["data"].take())
+        }
+        Some(WebCommMsgType::UpdateDeviceMetadata) => {
+            process_update_device_metadata(manager, zone, msg["data"].take())
+        }
+        Some(WebCommMsgType::MoveAnchor) => process_move_anchor(manager, zone, msg["data"].take()),
+        Some(WebCommMsgType::AddZone) => process_add_zone(manager, msg["data"].take()),
+        Some(WebCommMsgType::RemoveZone) => process_remove_zone(manager, msg["data"].take()),
+        Some(WebCommMsgType::SetZoneArea) => {
+            process_set_zone_area(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetGeofences) => {
+            process_set_geofences(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::GetHistory) => {
+            process_get_history(manager, zone, msg["data"].take(), sender)
+        }
+        Some(WebCommMsgType::SetGating) => {
+            process_set_gating(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetStationary) => {
+            process_set_stationary(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetCooperative) => {
+            process_set_cooperative(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetAnchorHealth) => {
+            process_set_anchor_health(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetBarometer) => {
+            process_set_barometer(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::CalibrateBarometer) => {
+            process_calibrate_barometer(manager, zone, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetFingerprints) => {
+            process_set_fingerprints(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::GetFingerprints) => {
+            process_get_fingerprints(selected_zone(manager, zone)?, sender)
+        }
+        Some(WebCommMsgType::RecordFingerprint) => {
+            process_record_fingerprint(manager, zone, msg["data"].take())
+        }
+        Some(WebCommMsgType::SetFingerprintConfig) => {
+            process_set_fingerprint_config(selected_zone(manager, zone)?, msg["data"].take())
+        }
+        Some(WebCommMsgType::GetAnchorHealth) => {
+            process_get_anchor_health(selected_zone(manager, zone)?, sender)
+        }
+        _ => Err(MessageFormat::Text("Unknown message type".to_string())),
+    }
+}
 
 pub fn parse(
-    zone: &mut engine::zone::Zone,
+    manager: &mut ZoneManager,
     cmd: &MessageFormat,
     sender: &SharedSender,
 ) -> Option<MessageTarget> {
     match cmd {
-        MessageFormat::Text(txt) => info!("zone {} received web txt cmd {}", zone.id, txt),
-        MessageFormat::Bin(bin) => info!("zone {} received web bin cmd {:?}", zone.id, bin),
+        MessageFormat::Text(txt) => {
+            info!("received web txt cmd {}", txt);
+            let json: serde_json::Value = match serde_json::from_str(txt) {
+                Ok(j) => j,
+                Err(e) => {
+                    error!("Received invalid JSON, {}", e);
+                    let msg = MessageFormat::Text(format!("Invalid JSON `{}`!", txt));
+                    return Some(MessageTarget::Direct(msg, sender.clone()));
+                }
+            };
+            match process_json(manager, json, sender) {
+                Ok(Some(msg)) => return Some(msg),
+                Ok(None) => (),
+                Err(msg) => return Some(MessageTarget::Direct(msg, sender.clone())),
+            }
+        }
+        MessageFormat::Bin(bin) => info!("received web bin cmd {:?}", bin),
     }
     Some(MessageTarget::Direct(
         MessageFormat::Text("Yes, sir!".to_string()),
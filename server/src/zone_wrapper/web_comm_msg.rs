@@ -0,0 +1,87 @@
+use engine::device::Metadata;
+use engine::map::Polygon;
+use engine::site::Placement;
+use engine::utils::{Coords, DevId, Timestamp, ZoneId};
+use num_derive::FromPrimitive;
+use serde_derive::Deserialize;
+
+#[derive(FromPrimitive)]
+pub enum WebCommMsgType {
+    SetGeoReference = 1,
+    SetObstacleMap = 2,
+    SetTracker = 3,
+    RemoveDevice = 4,
+    UpdateDeviceMetadata = 5,
+    MoveAnchor = 6,
+    AddZone = 7,
+    RemoveZone = 8,
+    SetZoneArea = 9,
+    SetGeofences = 10,
+    GetHistory = 11,
+    SetGating = 12,
+    SetStationary = 13,
+    SetCooperative = 14,
+    SetAnchorHealth = 15,
+    GetAnchorHealth = 16,
+    SetBarometer = 17,
+    CalibrateBarometer = 18,
+    SetFingerprints = 19,
+    GetFingerprints = 20,
+    RecordFingerprint = 21,
+    SetFingerprintConfig = 22,
+}
+
+#[derive(Deserialize)]
+pub struct WebCommDevice {
+    pub id: DevId,
+}
+
+#[derive(Deserialize)]
+pub struct WebCommDeviceMetadata {
+    pub id: DevId,
+    pub metadata: Metadata,
+}
+
+#[derive(Deserialize)]
+pub struct WebCommMoveAnchor {
+    pub id: DevId,
+    pub pos: Coords,
+    #[serde(default)]
+    pub timestamp: Timestamp,
+}
+
+#[derive(Deserialize)]
+pub struct WebCommZone {
+    pub id: ZoneId,
+}
+
+#[derive(Deserialize)]
+pub struct WebCommZoneArea {
+    #[serde(default)]
+    pub placement: Placement,
+    pub boundary: Option<Polygon>,
+}
+
+#[derive(Deserialize)]
+pub struct WebCommHistory {
+    pub id: DevId,
+    pub from: Timestamp,
+    pub to: Timestamp,
+    /// minimal time between returned positions, all when not set
+    #[serde(default)]
+    pub interval: Option<Timestamp>,
+}
+
+#[derive(Deserialize)]
+pub struct WebCommCalibrateBarometer {
+    pub id: DevId,
+    /// known height of the tag [m]
+    pub z: f32,
+}
+
+#[derive(Deserialize)]
+pub struct WebCommRecordFingerprint {
+    pub id: DevId,
+    /// surveyed position of the tag
+    pub pos: Coords,
+}
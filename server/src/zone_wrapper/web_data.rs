@@ -6,13 +6,13 @@ use super::messages::*;
 use log::info;
 
 pub fn parse(
-    zone: &mut engine::zone::Zone,
+    _manager: &mut engine::manager::ZoneManager,
     cmd: &MessageFormat,
     _: &SharedSender,
 ) -> Option<MessageTarget> {
     match cmd {
-        MessageFormat::Text(txt) => info!("zone {} received dev txt data {}", zone.id, txt),
-        MessageFormat::Bin(bin) => info!("zone {} received dev bin data {:?}", zone.id, bin),
+        MessageFormat::Text(txt) => info!("received web txt data {}", txt),
+        MessageFormat::Bin(bin) => info!("received web bin data {:?}", bin),
     }
     None
 }